@@ -4,13 +4,24 @@ use std::{
 };
 
 use anyhow::Context;
-use structopt::StructOpt;
+use structopt::{
+    clap::{AppSettings, ErrorKind},
+    StructOpt,
+};
 use tree_sitter_reduce::{
-    passes::generic::{DiscardWhitespace, TreeSitterReplace},
-    ShellTest,
+    passes::generic::{DiscardWhitespace, TreeSitterGroupReplace, TreeSitterReplace},
+    Pass, ShellTest,
 };
 
+mod pass_file;
+mod passes;
+mod selftest;
+mod util;
+
 #[derive(Debug, structopt::StructOpt)]
+#[structopt(
+    after_help = "A test script named `selftest` must be passed as `./selftest`, so as not to be mistaken for the `selftest` subcommand."
+)]
 struct Opt {
     /// Path to the test that validates whether the input is interesting
     ///
@@ -23,118 +34,825 @@ struct Opt {
     /// in any way that could corrupt further runs! The working directory is NOT
     /// cleared between each run; this is on purpose seeing how incremental
     /// compilation makes each test much faster when dealing with large reproducers.
+    ///
+    /// Run `rsreduce selftest` instead to check that reductions with the same
+    /// random seed are reproducible.
     test: PathBuf,
 
+    /// Subdirectory of the root path in which to run the test
+    ///
+    /// By default, the test is run at the root of the crate (or workspace). This
+    /// can be used to run it from within one crate of a workspace, for instance.
+    #[structopt(long)]
+    test_workdir: Option<PathBuf>,
+
+    /// Skip running the test on edits that make the edited file fail to parse
+    ///
+    /// This makes reduction faster, by not running the test on inputs that would
+    /// most likely not compile anyway. However, it should not be used if the files
+    /// to reduce do not already parse cleanly, as all edits to them would then be
+    /// rejected.
+    #[structopt(long)]
+    smoke_test: bool,
+
+    /// File in which to save the output of the most recent test run
+    ///
+    /// The file is overwritten after each test run that was not interrupted. This
+    /// is useful to figure out why the reduction is not progressing, for instance
+    /// when the test fails for an unexpected reason.
+    #[structopt(long)]
+    debug_last_output: Option<PathBuf>,
+
+    /// Run each test in a fresh copy of the crate, instead of reusing the same one
+    ///
+    /// This makes each test much slower, as compilation can no longer be
+    /// incremental, but can help if leftover build artifacts make the test flaky.
+    #[structopt(long)]
+    hermetic_test: bool,
+
+    /// Name of an attribute that the attribute removal pass must keep
+    ///
+    /// Pass multiple times to keep multiple attributes. This is useful for the
+    /// attributes that are essential to reproducing, like `repr` or `no_std`.
+    /// Paths are compared as written in the source, eg. `serde::rename`.
+    #[structopt(long = "keep-attribute")]
+    keep_attributes: Vec<String>,
+
+    /// Features to consider enabled, as a comma-separated list
+    ///
+    /// When set, `#[cfg(feature = "...")]` attributes get applied: code gated
+    /// behind other features is removed, and the attributes gating code behind
+    /// these features are removed. Pass an empty string to consider all the
+    /// features disabled.
+    #[structopt(long, use_delimiter = true)]
+    active_features: Option<Vec<String>>,
+
+    /// File from which to load additional passes
+    ///
+    /// Each pass is defined by a tree-sitter query and a replacement template, see
+    /// `rsreduce/src/pass_file.rs` for the format of this file. The passes are run
+    /// in addition to the built-in ones.
+    #[structopt(long)]
+    passes_from: Option<PathBuf>,
+
     #[structopt(flatten)]
     other_opts: tree_sitter_reduce::Opt,
 }
 
 fn main() -> anyhow::Result<()> {
-    let opt = Opt::from_args();
+    // The subcommand lifts the requirements of the reduction arguments, that are
+    // thus only parsed without one
+    let matches = Opt::clap()
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(selftest::SelftestOpt::clap().name("selftest"))
+        .get_matches_safe()
+        .or_else(|e| match e.kind {
+            // Tests named like the subcommand, eg. `./selftest`, are not typos of it
+            ErrorKind::InvalidSubcommand => Opt::clap().get_matches_safe().map_err(|_| e),
+            _ => Err(e),
+        })
+        .unwrap_or_else(|e| e.exit());
+    if let Some(matches) = matches.subcommand_matches("selftest") {
+        let opt = selftest::SelftestOpt::from_clap(matches);
+        return selftest::run(opt, &reduction_passes(Vec::new(), None), &polish_passes());
+    }
+    let opt = Opt::from_clap(&matches);
     let test = opt
         .test
         .canonicalize()
         .with_context(|| format!("canonicalizing path {:?}", opt.test))?;
     // Rust testing needs no generic prep/cleanup
-    let test = ShellTest::new(test).with_snapshot_cleanup(remove_target_dir);
-    tree_sitter_reduce::run(
-        opt.other_opts,
-        list_files,
-        test,
-        &[
-            Arc::new(DiscardWhitespace),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Remove random nodes"),
-                node_matcher: |_, n| n.is_named().then(Vec::new),
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Loopify"),
-                node_matcher: |_, n| {
-                    match n.kind() {
-                        "block" => true,
-                        k if k.ends_with("_expression") => true,
-                        _ => false,
+    let smoke_test = opt.smoke_test;
+    let mut test = ShellTest::new(test)
+        .with_snapshot_cleanup(remove_target_dir)
+        .with_smoke_test(move |root: &Path, edited: &Path| {
+            // The workspace member removal removes the edited file
+            let path = root.join(edited);
+            Ok(!smoke_test || !path.exists() || parses_cleanly(&path)?)
+        });
+    if let Some(workdir) = opt.test_workdir {
+        test = test.with_workdir(workdir)?;
+    }
+    if let Some(path) = opt.debug_last_output {
+        test = test.with_debug_output(path);
+    }
+    if opt.hermetic_test {
+        test = test.with_hermetic(opt.other_opts.tmp_dir.clone());
+    }
+    let mut passes = reduction_passes(opt.keep_attributes, opt.active_features);
+    if let Some(path) = &opt.passes_from {
+        passes.extend(pass_file::load_passes(path)?);
+    }
+    tree_sitter_reduce::run(opt.other_opts, list_files, test, &passes, &polish_passes())
+}
+
+/// The passes used for reduction, keeping the attributes named in `keep_attributes`
+///
+/// The `cfg(feature)` attributes are only applied if `active_features` is set.
+fn reduction_passes(
+    keep_attributes: Vec<String>,
+    active_features: Option<Vec<String>>,
+) -> Vec<Arc<dyn Pass>> {
+    let keep_derives = keep_attributes.iter().any(|k| k == "derive");
+    let keep_attribute_args = keep_attributes.clone();
+    let lint_levels = [b"allow".as_slice(), b"deny", b"warn", b"forbid"]
+        .into_iter()
+        .filter(|l| !keep_attributes.iter().any(|k| k.as_bytes() == *l))
+        .collect::<Vec<_>>();
+    let hints = [
+        b"inline".as_slice(),
+        b"cold",
+        b"track_caller",
+        b"no_mangle",
+        b"must_use",
+    ]
+    .into_iter()
+    .filter(|h| !keep_attributes.iter().any(|k| k.as_bytes() == *h))
+    .collect::<Vec<_>>();
+    let active_features =
+        active_features.map(|f| f.into_iter().filter(|f| !f.is_empty()).collect::<Vec<_>>());
+    let mut res: Vec<Arc<dyn Pass>> = vec![
+        Arc::new(DiscardWhitespace {
+            extensions: &["rs"],
+        }),
+        Arc::new(passes::GutBuildScript),
+        Arc::new(passes::RemoveWorkspaceMember),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove random nodes"),
+            node_matcher: |_, n| n.is_named().then(Vec::new),
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Loopify"),
+            node_matcher: |_, n| {
+                match n.kind() {
+                    "block" => true,
+                    k if k.ends_with("_expression") => true,
+                    _ => false,
+                }
+                .then(|| b"{loop{}}".to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Lower for loops"),
+            group_finder: |_: &[u8], root: &tree_sitter::Node| {
+                let mut res = Vec::new();
+                for n in util::descendants(root)
+                    .iter()
+                    .filter(|n| n.kind() == "for_expression")
+                {
+                    res.push(vec![(n.byte_range(), b"loop{}".to_vec())]);
+                    // Loops used as values cannot be removed, only replaced
+                    if n.parent()
+                        .map_or(false, |p| p.kind() == "expression_statement")
+                    {
+                        res.push(vec![(n.byte_range(), Vec::new())]);
                     }
-                    .then(|| b"{loop{}}".to_vec())
-                },
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Depublify"),
-                node_matcher: |_, n| (n.kind() == "visibility_modifier").then(Vec::new),
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Decommentify"),
-                node_matcher: |_, n| n.kind().ends_with("_comment").then(Vec::new),
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Remove items"),
-                node_matcher: |_, n| {
-                    (n.kind().ends_with("_item") || n.kind() == "macro_definition").then(Vec::new)
-                },
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Remove use declarations"),
-                node_matcher: |_, n| (n.kind() == "use_declaration").then(Vec::new),
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Remove argument types"),
-                node_matcher: |_, n| {
-                    ((n.kind().ends_with("type_identifier") || n.kind().ends_with("_type"))
-                        && n.parent()
-                            .and_then(|n| n.parent())
-                            .map(|n| {
-                                n.kind() == "function_signature_item" || n.kind() == "function_item"
-                            })
-                            .unwrap_or(false))
-                    .then(|| b"impl Sized".to_vec())
-                },
-                try_match_all_nodes: false,
-            }),
-            Arc::new(TreeSitterReplace {
-                language: tree_sitter_rust::language(),
-                name: String::from("Add default methods to function signatures"),
-                node_matcher: |i, n| {
-                    if n.kind() != "function_signature_item" {
-                        return None;
+                }
+                res
+            },
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Blank impl method bodies"),
+            node_matcher: |_, n| {
+                let function = n.parent()?;
+                let is_method_body = function.kind() == "function_item"
+                    && function.child_by_field_name("body")? == *n
+                    && function.parent()?.parent()?.kind() == "impl_item"
+                    && n.named_child_count() > 0;
+                is_method_body.then(|| b"{todo!()}".to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Hoist subexpressions"),
+            group_finder: passes::hoist_subexpressions,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Depublify"),
+            node_matcher: |_, n| (n.kind() == "visibility_modifier").then(Vec::new),
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Decommentify"),
+            node_matcher: |_, n| n.kind().ends_with("_comment").then(Vec::new),
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove doc comments"),
+            node_matcher: |i, n| {
+                // Doc comments can contain doctests, that are compiled code
+                let is_doc = match n.kind() {
+                    "line_comment" | "block_comment" => {
+                        let text = &i[n.byte_range()];
+                        let is_outer = (text.starts_with(b"///") && !text.starts_with(b"////"))
+                            || (text.starts_with(b"/**")
+                                && !text.starts_with(b"/***")
+                                && text != b"/**/");
+                        is_outer || text.starts_with(b"//!") || text.starts_with(b"/*!")
                     }
-                    let signature = &i[n.byte_range()];
-                    let mut signature = match signature.iter().rposition(|&b| b == b';') {
-                        None => return None,
-                        Some(i) => Vec::from(&signature[..i]),
-                    };
-                    signature.extend_from_slice(b"{loop{}}");
-                    Some(signature)
-                },
-                try_match_all_nodes: false,
-            }),
-            // TODO: Remove struct fields
-            // TODO: Replace match branches with a default branch (that loops)
-            // TODO: Do not try removing argument types in trait methods
-            // TODO: Defaultify, like Loopify but generates {Default::default()}
-            // TODO: Figure out a way to use LSP to delete all unused things?
-            // TODO: Try to figure out more things from [1] that could be automated
-            // [1] https://blog.pnkfx.org/blog/2019/11/18/rust-bug-minimization-patterns/
-        ],
-    )
+                    "attribute_item" | "inner_attribute_item" => {
+                        &i[n.named_child(0)?.named_child(0)?.byte_range()] == b"doc"
+                    }
+                    _ => false,
+                };
+                is_doc.then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove Drop, Deref and other special trait impls"),
+            node_matcher: |i, n| {
+                if n.kind() != "impl_item" {
+                    return None;
+                }
+                // These impls change runtime behavior, so removing them can also
+                // change how a crash reproduces
+                let name = util::trait_name_of(&n.child_by_field_name("trait")?)?;
+                matches!(
+                    &i[name.byte_range()],
+                    b"Drop"
+                        | b"Deref"
+                        | b"DerefMut"
+                        | b"AsRef"
+                        | b"AsMut"
+                        | b"Borrow"
+                        | b"BorrowMut"
+                        | b"Index"
+                        | b"IndexMut"
+                        | b"Display"
+                        | b"Debug"
+                        | b"Send"
+                        | b"Sync"
+                        | b"Unpin"
+                )
+                .then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove blanket impls"),
+            node_matcher: |i, n| {
+                if n.kind() != "impl_item" || n.child_by_field_name("trait").is_none() {
+                    return None;
+                }
+                // `impl<T> Trait for T`, or for `&T`, `&mut T`, `*const T`, ...
+                let mut ty = n.child_by_field_name("type")?;
+                while matches!(ty.kind(), "reference_type" | "pointer_type") {
+                    ty = ty.child_by_field_name("type")?;
+                }
+                if ty.kind() != "type_identifier" {
+                    return None;
+                }
+                let params = n.child_by_field_name("type_parameters")?;
+                let mut cursor = params.walk();
+                let is_blanket = params
+                    .named_children(&mut cursor)
+                    .any(|p| util::type_param_name(i, &p) == Some(&i[ty.byte_range()]));
+                is_blanket.then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove items"),
+            node_matcher: |_, n| {
+                (n.kind().ends_with("_item") || n.kind() == "macro_definition").then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(passes::RemoveOrphanImpls),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove use declarations"),
+            node_matcher: |_, n| (n.kind() == "use_declaration").then(Vec::new),
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove names from grouped imports"),
+            group_finder: passes::remove_use_list_items,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove extern crates and macro_use"),
+            node_matcher: |i, n| {
+                let is_macro_use = || {
+                    let attribute = n.named_child(0)?;
+                    Some(&i[attribute.named_child(0)?.byte_range()] == b"macro_use")
+                };
+                match n.kind() {
+                    "extern_crate_declaration" => Some(Vec::new()),
+                    "attribute_item" if is_macro_use()? => Some(Vec::new()),
+                    _ => None,
+                }
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove argument types"),
+            node_matcher: |_, n| {
+                ((n.kind().ends_with("type_identifier") || n.kind().ends_with("_type"))
+                    && n.parent()
+                        .and_then(|n| n.parent())
+                        .map(|n| {
+                            n.kind() == "function_signature_item" || n.kind() == "function_item"
+                        })
+                        .unwrap_or(false))
+                .then(|| b"impl Sized".to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Add default methods to function signatures"),
+            node_matcher: |i, n| {
+                if n.kind() != "function_signature_item" {
+                    return None;
+                }
+                let signature = &i[n.byte_range()];
+                let mut signature = match signature.iter().rposition(|&b| b == b';') {
+                    None => return None,
+                    Some(i) => Vec::from(&signature[..i]),
+                };
+                signature.extend_from_slice(b"{loop{}}");
+                Some(signature)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove trait associated items and their impls"),
+            group_finder: passes::remove_assoc_items,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove or simplify generic associated types"),
+            group_finder: passes::remove_gats,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove redundant default method bodies"),
+            node_matcher: passes::remove_default_bodies,
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove Self bounds"),
+            group_finder: passes::remove_self_bounds,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove individual bounds"),
+            group_finder: passes::remove_bounds,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove associated type equalities"),
+            group_finder: passes::remove_type_bindings,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove return types"),
+            group_finder: |_: &[u8], root: &tree_sitter::Node| {
+                util::descendants(root)
+                    .into_iter()
+                    .filter(|n| n.kind() == "function_item")
+                    .filter_map(|n| {
+                        let return_type = n.child_by_field_name("return_type")?;
+                        let mut cursor = n.walk();
+                        let arrow = n.children(&mut cursor).find(|c| c.kind() == "->")?;
+                        Some(vec![(
+                            arrow.start_byte()..return_type.end_byte(),
+                            Vec::new(),
+                        )])
+                    })
+                    .collect()
+            },
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove type annotations"),
+            node_matcher: passes::remove_type_annotations,
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Shorten paths"),
+            node_matcher: passes::shorten_paths,
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Unbox types"),
+            node_matcher: |i, n| {
+                if n.kind() != "generic_type" {
+                    return None;
+                }
+                let ty = n.child_by_field_name("type")?;
+                let name = match ty.kind() {
+                    "type_identifier" => ty,
+                    "scoped_type_identifier" => ty.child_by_field_name("name")?,
+                    _ => return None,
+                };
+                let args = n.child_by_field_name("type_arguments")?;
+                if &i[name.byte_range()] != b"Box" || args.named_child_count() != 1 {
+                    return None;
+                }
+                Some(i[args.named_child(0)?.byte_range()].to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Replace trait objects with a concrete type"),
+            group_finder: passes::replace_trait_objects,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Replace trait objects with unit"),
+            node_matcher: |_, n| (n.kind() == "dynamic_type").then(|| b"()".to_vec()),
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Replace parameter patterns with wildcards"),
+            node_matcher: |_, n| {
+                let parent = n.parent()?;
+                if parent.kind() != "parameter"
+                    || parent.child_by_field_name("pattern")? != *n
+                    || matches!(n.kind(), "identifier" | "_")
+                {
+                    return None;
+                }
+                Some(b"_".to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove unused function parameters"),
+            group_finder: passes::remove_unused_params,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Inline single-use let bindings"),
+            group_finder: passes::inline_lets,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove constants and their uses"),
+            group_finder: passes::remove_consts,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove unused type parameters"),
+            group_finder: passes::remove_unused_type_params,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove type parameter defaults"),
+            node_matcher: |i, n| {
+                if n.kind() != "optional_type_parameter" {
+                    return None;
+                }
+                // `T: Bound = Default` becomes `T: Bound`
+                Some(i[n.child_by_field_name("name")?.byte_range()].to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Monomorphize generic functions"),
+            group_finder: passes::monomorphize_functions,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Turn bounded type parameters into impl Trait arguments"),
+            group_finder: passes::impl_trait_params,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove const generic parameters"),
+            group_finder: passes::remove_const_params,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove tuple struct and variant fields"),
+            group_finder: passes::remove_tuple_fields,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove PhantomData fields"),
+            group_finder: passes::remove_phantom_fields,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove else if arms"),
+            node_matcher: |i, n| {
+                if n.kind() != "else_clause" {
+                    return None;
+                }
+                let arm = n.named_child(0).filter(|c| c.kind() == "if_expression")?;
+                match arm.child_by_field_name("alternative") {
+                    Some(rest) => Some(i[rest.byte_range()].to_vec()),
+                    None => Some(Vec::new()),
+                }
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Reduce patterns"),
+            group_finder: passes::reduce_patterns,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove fields overriding the base of struct expressions"),
+            group_finder: passes::remove_overridden_fields,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove question marks"),
+            node_matcher: |i, n| {
+                (n.kind() == "try_expression")
+                    .then(|| n.named_child(0))
+                    .flatten()
+                    .map(|e| i[e.byte_range()].to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Unwrap question marks"),
+            node_matcher: |i, n| {
+                (n.kind() == "try_expression")
+                    .then(|| n.named_child(0))
+                    .flatten()
+                    .map(|e| [&i[e.byte_range()], b".unwrap()"].concat())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove type casts"),
+            node_matcher: |i, n| {
+                (n.kind() == "type_cast_expression")
+                    .then(|| n.child_by_field_name("value"))
+                    .flatten()
+                    .map(|e| i[e.byte_range()].to_vec())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove move from closures"),
+            node_matcher: |_, n| {
+                let is_closure_move = n.kind() == "move"
+                    && matches!(n.parent()?.kind(), "closure_expression" | "async_block");
+                is_closure_move.then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Unwrap unsafe blocks"),
+            node_matcher: |i, n| {
+                if n.kind() != "unsafe_block" {
+                    return None;
+                }
+                let mut cursor = n.walk();
+                let block = n
+                    .named_children(&mut cursor)
+                    .find(|c| c.kind() == "block")?;
+                // In statement position, the contents can be merged into the parent
+                // block, as long as they do not declare anything that could clash and
+                // do not end with a tail expression
+                let is_statement = matches!(n.parent()?.kind(), "expression_statement" | "block");
+                let only_statements = block
+                    .named_children(&mut cursor)
+                    .all(|c| c.kind() == "expression_statement");
+                match is_statement && only_statements {
+                    true => Some(i[(block.start_byte() + 1)..(block.end_byte() - 1)].to_vec()),
+                    false => Some(i[block.byte_range()].to_vec()),
+                }
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Simplify formatting macros"),
+            node_matcher: |i, n| {
+                if n.kind() != "macro_invocation" {
+                    return None;
+                }
+                let name = &i[n.child_by_field_name("macro")?.byte_range()];
+                let args: &[u8] = match name {
+                    b"println" | b"eprintln" => b"()",
+                    b"print" | b"eprint" | b"format" => b"(\"\")",
+                    _ => return None,
+                };
+                Some([name, b"!", args].concat())
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove hint attributes"),
+            node_matcher: move |i, n| {
+                if n.kind() != "attribute_item" {
+                    return None;
+                }
+                let name = &i[n.named_child(0)?.named_child(0)?.byte_range()];
+                hints.contains(&name).then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove lint level attributes"),
+            node_matcher: move |i, n| {
+                if !matches!(n.kind(), "attribute_item" | "inner_attribute_item") {
+                    return None;
+                }
+                // They can hide or turn into errors the diagnostics being reduced
+                let name = &i[n.named_child(0)?.named_child(0)?.byte_range()];
+                lint_levels.contains(&name).then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove derives"),
+            node_matcher: move |i, n| {
+                if n.kind() != "attribute_item" || keep_derives {
+                    return None;
+                }
+                let name = &i[n.named_child(0)?.named_child(0)?.byte_range()];
+                (name == b"derive").then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove attributes"),
+            node_matcher: move |i, n| {
+                if !matches!(n.kind(), "attribute_item" | "inner_attribute_item") {
+                    return None;
+                }
+                let name = &i[n.named_child(0)?.named_child(0)?.byte_range()];
+                let keep = keep_attributes.iter().any(|k| k.as_bytes() == name);
+                (!keep).then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove attribute arguments"),
+            group_finder: move |i: &[u8], n: &tree_sitter::Node| {
+                passes::reduce_attribute_args(i, n, &keep_attribute_args)
+            },
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove statement and expression attributes"),
+            node_matcher: |_, n| {
+                if n.kind() != "attribute_item" {
+                    return None;
+                }
+                let attributes_statement = match n.parent()?.kind() {
+                    "block" => !n.next_named_sibling()?.kind().ends_with("_item"),
+                    "match_arm" => true,
+                    _ => false,
+                };
+                attributes_statement.then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        // TODO: Remove struct fields
+        // TODO: Replace match branches with a default branch (that loops)
+        // TODO: Do not try removing argument types in trait methods
+        // TODO: Defaultify, like Loopify but generates {Default::default()}
+        // TODO: Figure out a way to use LSP to delete all unused things?
+        // TODO: Try to figure out more things from [1] that could be automated
+        // [1] https://blog.pnkfx.org/blog/2019/11/18/rust-bug-minimization-patterns/
+    ];
+    if let Some(features) = active_features {
+        res.push(Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Apply cfg(feature) attributes"),
+            group_finder: move |i: &[u8], n: &tree_sitter::Node| {
+                passes::apply_cfg_features(i, n, &features)
+            },
+        }));
+    }
+    res
+}
+
+/// The passes run once on each file after the reduction converged
+fn polish_passes() -> Vec<Arc<dyn Pass>> {
+    vec![
+        Arc::new(TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Shorten local variable names"),
+            group_finder: passes::shorten_identifiers,
+        }),
+        Arc::new(TreeSitterReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove empty statements"),
+            node_matcher: |_, n| {
+                let is_empty = match n.kind() {
+                    "empty_statement" => true,
+                    // `();` and `{}`
+                    "expression_statement" => {
+                        let e = n.named_child(0)?;
+                        e.kind() == "unit_expression"
+                            || (e.kind() == "block" && e.child_count() == 2)
+                    }
+                    _ => false,
+                };
+                is_empty.then(Vec::new)
+            },
+            try_match_all_nodes: false,
+        }),
+        Arc::new(DiscardWhitespace {
+            extensions: &["rs"],
+        }),
+    ]
 }
 
 fn list_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
-    // TODO: Also support reducing the toml files, to remove external deps? This will
-    // need additional infra in tree-sitter-reduce, to support a different selection
-    // of passes per file type.
+    // TODO: Also support reducing the toml files, to remove external deps? The rust
+    // passes already declare that they only apply to `.rs` files.
     let mut res = Vec::new();
     for file in walkdir::WalkDir::new(root) {
         let file =
@@ -153,6 +871,18 @@ fn list_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
     Ok(res)
 }
 
+fn parses_cleanly(path: &Path) -> anyhow::Result<bool> {
+    let contents = std::fs::read(path).with_context(|| format!("reading file {path:?}"))?;
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(tree_sitter_rust::language())
+        .expect("Failed to make a parser for rust");
+    Ok(parser
+        .parse(&contents, None)
+        .map(|t| !t.root_node().has_error())
+        .unwrap_or(false))
+}
+
 fn remove_target_dir(root: &Path) -> anyhow::Result<()> {
     let target_dir = root.join("target");
     if let Ok(_) = std::fs::metadata(target_dir) {
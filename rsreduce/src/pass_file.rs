@@ -0,0 +1,85 @@
+//! Loading of user-defined passes from a file
+//!
+//! The file is a list of passes separated by empty lines. Each pass is made of
+//! `key: value` lines, with the following keys:
+//! - `name`: the human-readable name of the pass
+//! - `query`: a tree-sitter query, that must capture the nodes to replace as `@replace`
+//! - `replacement` (optional, defaults to empty): the text to replace the nodes with,
+//!   where `{name}` is substituted with the text of the node captured as `@name`
+//!
+//! Lines that start with whitespace continue the value of the previous line, and
+//! lines that start with `#` are comments. For instance:
+//!
+//! ```text
+//! # Replace all calls to `foo` with their first argument
+//! name: Unwrap foo calls
+//! query: (call_expression
+//!          function: (identifier) @f (#eq? @f "foo")
+//!          arguments: (arguments . (_) @arg)) @replace
+//! replacement: {arg}
+//! ```
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use tree_sitter_reduce::{passes::generic::TreeSitterQueryReplace, Pass};
+
+pub fn load_passes(path: &Path) -> anyhow::Result<Vec<Arc<dyn Pass>>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("reading pass file {path:?}"))?;
+    let mut res = Vec::new();
+    let mut fields = Vec::<(&str, String)>::new();
+    let mut first_line = 1;
+    for (i, line) in contents.lines().chain(std::iter::once("")).enumerate() {
+        let line_number = i + 1;
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            if !fields.is_empty() {
+                res.push(
+                    make_pass(std::mem::take(&mut fields))
+                        .with_context(|| format!("loading pass at {path:?}:{first_line}"))?,
+                );
+            }
+            first_line = line_number + 1;
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            let (_, value) = fields.last_mut().with_context(|| {
+                format!("{path:?}:{line_number}: continuation line without a previous key")
+            })?;
+            value.push('\n');
+            value.push_str(line.trim());
+            continue;
+        }
+        let (key, value) = line.split_once(':').with_context(|| {
+            format!("{path:?}:{line_number}: expected a `key: value` line, got {line:?}")
+        })?;
+        fields.push((key.trim(), String::from(value.trim())));
+    }
+    Ok(res)
+}
+
+fn make_pass(fields: Vec<(&str, String)>) -> anyhow::Result<Arc<dyn Pass>> {
+    let (mut name, mut query, mut replacement) = (None, None, None);
+    for (key, value) in fields {
+        let slot = match key {
+            "name" => &mut name,
+            "query" => &mut query,
+            "replacement" => &mut replacement,
+            _ => anyhow::bail!("unknown key {key:?}"),
+        };
+        anyhow::ensure!(slot.is_none(), "key {key:?} is set multiple times");
+        *slot = Some(value);
+    }
+    let name = name.context("missing key \"name\"")?;
+    let query = query.context("missing key \"query\"")?;
+    Ok(Arc::new(TreeSitterQueryReplace::new(
+        tree_sitter_rust::language(),
+        &["rs"],
+        name,
+        &query,
+        &replacement.unwrap_or_default(),
+    )?))
+}
@@ -0,0 +1,106 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::descendants;
+
+/// Returns the tokens of `token_tree`, without its delimiters
+fn inner_tokens<'tree>(token_tree: &tree_sitter::Node<'tree>) -> Vec<tree_sitter::Node<'tree>> {
+    let mut cursor = token_tree.walk();
+    let mut tokens = token_tree.children(&mut cursor).collect::<Vec<_>>();
+    if tokens.len() >= 2 {
+        tokens.pop();
+        tokens.remove(0);
+    }
+    tokens
+}
+
+/// Evaluates the cfg predicate made of `tokens`, given the `active` features
+///
+/// Only `feature = "..."` and their combinations with `not`, `all` and `any` are
+/// supported, any other predicate makes this return `None`.
+fn evaluate(input: &[u8], tokens: &[tree_sitter::Node], active: &[String]) -> Option<bool> {
+    match tokens {
+        [name, eq, value]
+            if &input[name.byte_range()] == b"feature"
+                && eq.kind() == "="
+                && value.kind() == "string_literal" =>
+        {
+            let value = &input[value.byte_range()];
+            let feature = value.get(1..value.len().saturating_sub(1))?;
+            Some(active.iter().any(|f| f.as_bytes() == feature))
+        }
+        [op, args] if args.kind() == "token_tree" => {
+            let args = inner_tokens(args);
+            let values = args
+                .split(|t| t.kind() == ",")
+                .filter(|e| !e.is_empty())
+                .map(|e| evaluate(input, e, active))
+                .collect::<Option<Vec<bool>>>()?;
+            match &input[op.byte_range()] {
+                b"not" if values.len() == 1 => Some(!values[0]),
+                b"all" => Some(values.iter().all(|v| *v)),
+                b"any" => Some(values.iter().any(|v| *v)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the byte range of the attribute and the code it applies to, that must
+/// be removed together if the attribute is a disabled cfg
+fn cfg_target(attribute: &tree_sitter::Node) -> Option<std::ops::Range<usize>> {
+    let parent = attribute.parent()?;
+    if parent.kind() == "match_arm" {
+        return Some(parent.byte_range());
+    }
+    let mut target = attribute.next_named_sibling()?;
+    while target.kind() == "attribute_item" || target.kind().ends_with("_comment") {
+        target = target.next_named_sibling()?;
+    }
+    // Fields, variants and parameters are followed by a separator
+    let end = match target.next_sibling() {
+        Some(next) if next.kind() == "," => next.end_byte(),
+        _ => target.end_byte(),
+    };
+    Some(attribute.start_byte()..end)
+}
+
+/// Apply the `#[cfg(feature = "...")]` attributes, given the `active` features
+///
+/// Code gated behind a disabled feature is removed along with its attribute, while
+/// the attribute alone is removed for enabled features. Each attribute is tried on
+/// its own, so that the features wrongly assumed to be active or inactive will just
+/// be rejected by the test. Other cfg predicates, like `test` or `unix`, are left
+/// alone.
+pub fn apply_cfg_features(
+    input: &[u8],
+    root: &tree_sitter::Node,
+    active: &[String],
+) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for attribute in descendants(root)
+        .iter()
+        .filter(|n| n.kind() == "attribute_item")
+    {
+        let Some(attr) = attribute.named_child(0) else {
+            continue;
+        };
+        let (Some(name), Some(args)) = (attr.named_child(0), attr.child_by_field_name("arguments"))
+        else {
+            continue;
+        };
+        if &input[name.byte_range()] != b"cfg" {
+            continue;
+        }
+        let range = match evaluate(input, &inner_tokens(&args), active) {
+            Some(true) => attribute.byte_range(),
+            Some(false) => match cfg_target(attribute) {
+                Some(range) => range,
+                None => continue,
+            },
+            None => continue,
+        };
+        res.push(vec![(range, Vec::new())]);
+    }
+    res
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use anyhow::Context;
+use tree_sitter_reduce::{Job, JobStatus, Pass, Test, TestResult};
+
+const EMPTY_BUILD_SCRIPT: &[u8] = b"fn main(){}";
+
+/// Replace the whole contents of `build.rs` files with an empty `main`
+///
+/// Build scripts are often irrelevant to the reproducer, in which case this gets
+/// rid of them in a single step.
+#[derive(Debug, Hash)]
+pub struct GutBuildScript;
+
+impl Pass for GutBuildScript {
+    fn reduce(
+        &self,
+        workdir: &Path,
+        test: &dyn Test,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        let path = &workdir.join(&job.path);
+        let file_contents =
+            std::fs::read(path).with_context(|| format!("reading file {path:?}"))?;
+
+        let attempt = "Gut build script";
+        if String::from_utf8_lossy(&file_contents).trim().as_bytes() == EMPTY_BUILD_SCRIPT {
+            return Ok(JobStatus::PassFailed(String::from(attempt)));
+        }
+        std::fs::write(path, EMPTY_BUILD_SCRIPT)
+            .with_context(|| format!("writing file {path:?}"))?;
+        match test
+            .test_interesting(workdir, kill_trigger, attempt, job.id(0))
+            .context("running the test")?
+        {
+            TestResult::Interesting => Ok(JobStatus::Reduced(String::from(attempt))),
+            TestResult::NotInteresting => Ok(JobStatus::DidNotReduce),
+            TestResult::Interrupted => Ok(JobStatus::Interrupted),
+        }
+    }
+
+    fn applies_to(&self, path: &Path) -> bool {
+        path.file_name().map_or(false, |n| n == "build.rs")
+    }
+}
@@ -0,0 +1,44 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::descendants;
+
+/// Replace expressions with one of their operands
+///
+/// For instance, `f(g(h(x)))` can become `g(h(x))`, `h(x)` or `x`, and `a + b` can
+/// become `a` or `b`. Each possible replacement is its own group, so that the
+/// dichotomy picks which operand replaces each expression.
+pub fn hoist_subexpressions(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for node in descendants(root) {
+        if !node.kind().ends_with("_expression") {
+            continue;
+        }
+        for operand in operands(&node) {
+            res.push(vec![(
+                node.byte_range(),
+                input[operand.byte_range()].to_vec(),
+            )]);
+        }
+    }
+    res
+}
+
+/// The operands of `node`, including the arguments of calls
+fn operands<'tree>(node: &tree_sitter::Node<'tree>) -> Vec<tree_sitter::Node<'tree>> {
+    let mut res = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "arguments" {
+            let mut cursor = child.walk();
+            res.extend(child.named_children(&mut cursor).filter(is_expression));
+        } else if is_expression(&child) {
+            res.push(child);
+        }
+    }
+    res
+}
+
+fn is_expression(node: &tree_sitter::Node) -> bool {
+    let kind = node.kind();
+    kind.ends_with("_expression") || kind.ends_with("_literal") || kind == "identifier"
+}
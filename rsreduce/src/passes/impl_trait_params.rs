@@ -0,0 +1,109 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, type_param_name, with_separator};
+
+/// Turn bounded function type parameters into `impl Trait` arguments
+///
+/// `fn f<T: Trait>(x: T)` becomes `fn f(x: impl Trait)`, with the bounds from the
+/// `where` clause merged in. This only applies to the parameters that are used once,
+/// in the type of an argument, as each `impl Trait` is a distinct type. The bounds
+/// can then get reduced further by the passes working on `impl Trait` types.
+pub fn impl_trait_params(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for function in descendants(root)
+        .iter()
+        .filter(|n| n.kind() == "function_item")
+    {
+        let (Some(type_params), Some(params)) = (
+            function.child_by_field_name("type_parameters"),
+            function.child_by_field_name("parameters"),
+        ) else {
+            continue;
+        };
+        let predicates = function
+            .children(&mut function.walk())
+            .find(|c| c.kind() == "where_clause")
+            .map(|w| {
+                let mut cursor = w.walk();
+                let predicates = w.named_children(&mut cursor).collect::<Vec<_>>();
+                (w, predicates)
+            });
+        let function_nodes = descendants(function);
+        let mut cursor = type_params.walk();
+        for param in type_params.named_children(&mut cursor) {
+            let Some(name) = type_param_name(input, &param) else {
+                continue;
+            };
+            let own_predicates = predicates.as_ref().map_or(Vec::new(), |(_, predicates)| {
+                predicates
+                    .iter()
+                    .filter(|p| {
+                        p.child_by_field_name("left")
+                            .map_or(false, |l| &input[l.byte_range()] == name)
+                    })
+                    .copied()
+                    .collect()
+            });
+            let bounds = std::iter::once(param)
+                .chain(own_predicates.iter().copied())
+                .filter_map(|p| p.child_by_field_name("bounds"))
+                .flat_map(|b| {
+                    let mut cursor = b.walk();
+                    let bounds = b
+                        .named_children(&mut cursor)
+                        .map(|b| &input[b.byte_range()])
+                        .collect::<Vec<_>>();
+                    bounds
+                })
+                .collect::<Vec<_>>();
+            if bounds.is_empty() {
+                continue;
+            }
+            let declarations = std::iter::once(param)
+                .chain(own_predicates.iter().copied())
+                .collect::<Vec<_>>();
+            let uses = function_nodes
+                .iter()
+                .filter(|n| {
+                    n.kind().ends_with("identifier")
+                        && &input[n.byte_range()] == name
+                        && !declarations
+                            .iter()
+                            .any(|d| d.byte_range().contains(&n.start_byte()))
+                })
+                .collect::<Vec<_>>();
+            let [ty] = uses[..] else {
+                continue;
+            };
+            if ty.kind() != "type_identifier" || !params.byte_range().contains(&ty.start_byte()) {
+                continue;
+            }
+
+            let mut group = match type_params.named_child_count() {
+                1 => vec![(type_params.byte_range(), Vec::new())],
+                _ => vec![(with_separator(&param, ","), Vec::new())],
+            };
+            if let Some((where_clause, predicates)) = &predicates {
+                match own_predicates.len() == predicates.len() {
+                    true if !own_predicates.is_empty() => {
+                        group.push((where_clause.byte_range(), Vec::new()))
+                    }
+                    _ => group.extend(
+                        own_predicates
+                            .iter()
+                            .map(|p| (with_separator(p, ","), Vec::new())),
+                    ),
+                }
+            }
+            let mut replacement = [b"impl ".as_slice(), &bounds.join(&b" + "[..])].concat();
+            // `&impl A + B` is ambiguous
+            let in_parameter = ty.parent().map_or(false, |p| p.kind() == "parameter");
+            if bounds.len() > 1 && !in_parameter {
+                replacement = [b"(", &replacement[..], b")"].concat();
+            }
+            group.push((ty.byte_range(), replacement));
+            res.push(group);
+        }
+    }
+    res
+}
@@ -0,0 +1,77 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::descendants;
+
+/// Expression kinds that can be substituted anywhere without adding parentheses
+const ATOMIC_EXPRESSIONS: &[&str] = &[
+    "identifier",
+    "integer_literal",
+    "float_literal",
+    "string_literal",
+    "raw_string_literal",
+    "char_literal",
+    "boolean_literal",
+    "unit_expression",
+    "call_expression",
+    "macro_invocation",
+    "field_expression",
+    "index_expression",
+    "parenthesized_expression",
+    "tuple_expression",
+    "array_expression",
+    "block",
+];
+
+/// Inline `let` bindings whose identifier is used exactly once afterwards in the same block
+///
+/// The use count is best-effort, as it only looks at identifiers with the same name.
+pub fn inline_lets(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for block in descendants(root)
+        .into_iter()
+        .filter(|n| n.kind() == "block")
+    {
+        let mut cursor = block.walk();
+        let statements = block.named_children(&mut cursor).collect::<Vec<_>>();
+        for (idx, stmt) in statements.iter().enumerate() {
+            if stmt.kind() != "let_declaration" {
+                continue;
+            }
+            let (Some(pattern), Some(value)) = (
+                stmt.child_by_field_name("pattern"),
+                stmt.child_by_field_name("value"),
+            ) else {
+                continue;
+            };
+            if pattern.kind() != "identifier" {
+                continue;
+            }
+            let name = &input[pattern.byte_range()];
+            let uses = statements[(idx + 1)..]
+                .iter()
+                .flat_map(descendants)
+                .filter(|n| n.kind() == "identifier" && &input[n.byte_range()] == name)
+                .collect::<Vec<_>>();
+            let [usage] = &uses[..] else {
+                continue;
+            };
+            if usage
+                .parent()
+                .map(|p| p.kind() == "shorthand_field_initializer")
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let value_bytes = &input[value.byte_range()];
+            let replace_with = match ATOMIC_EXPRESSIONS.contains(&value.kind()) {
+                true => value_bytes.to_vec(),
+                false => [b"(", value_bytes, b")"].concat(),
+            };
+            res.push(vec![
+                (stmt.byte_range(), Vec::new()),
+                (usage.byte_range(), replace_with),
+            ]);
+        }
+    }
+    res
+}
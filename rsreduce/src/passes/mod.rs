@@ -0,0 +1,57 @@
+mod apply_cfg_features;
+mod gut_build_script;
+mod hoist_subexpressions;
+mod impl_trait_params;
+mod inline_lets;
+mod monomorphize_functions;
+mod reduce_attribute_args;
+mod reduce_patterns;
+mod remove_assoc_items;
+mod remove_bounds;
+mod remove_const_params;
+mod remove_consts;
+mod remove_default_bodies;
+mod remove_gats;
+mod remove_orphan_impls;
+mod remove_overridden_fields;
+mod remove_phantom_fields;
+mod remove_self_bounds;
+mod remove_tuple_fields;
+mod remove_type_annotations;
+mod remove_type_bindings;
+mod remove_unused_params;
+mod remove_unused_type_params;
+mod remove_use_list_items;
+mod remove_workspace_member;
+mod replace_trait_objects;
+mod shorten_identifiers;
+mod shorten_paths;
+
+pub use apply_cfg_features::apply_cfg_features;
+pub use gut_build_script::GutBuildScript;
+pub use hoist_subexpressions::hoist_subexpressions;
+pub use impl_trait_params::impl_trait_params;
+pub use inline_lets::inline_lets;
+pub use monomorphize_functions::monomorphize_functions;
+pub use reduce_attribute_args::reduce_attribute_args;
+pub use reduce_patterns::reduce_patterns;
+pub use remove_assoc_items::remove_assoc_items;
+pub use remove_bounds::remove_bounds;
+pub use remove_const_params::remove_const_params;
+pub use remove_consts::remove_consts;
+pub use remove_default_bodies::remove_default_bodies;
+pub use remove_gats::remove_gats;
+pub use remove_orphan_impls::RemoveOrphanImpls;
+pub use remove_overridden_fields::remove_overridden_fields;
+pub use remove_phantom_fields::remove_phantom_fields;
+pub use remove_self_bounds::remove_self_bounds;
+pub use remove_tuple_fields::remove_tuple_fields;
+pub use remove_type_annotations::remove_type_annotations;
+pub use remove_type_bindings::remove_type_bindings;
+pub use remove_unused_params::remove_unused_params;
+pub use remove_unused_type_params::remove_unused_type_params;
+pub use remove_use_list_items::remove_use_list_items;
+pub use remove_workspace_member::RemoveWorkspaceMember;
+pub use replace_trait_objects::replace_trait_objects;
+pub use shorten_identifiers::shorten_identifiers;
+pub use shorten_paths::shorten_paths;
@@ -0,0 +1,114 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{called_name, descendants, type_param_name, with_separator};
+
+/// Returns the non-lifetime arguments of all the turbofish calls to function `name`
+///
+/// Calls with a number of arguments other than `arity` are ignored, as they are most
+/// likely calls to another function with the same name.
+fn turbofish_calls<'tree>(
+    input: &[u8],
+    nodes: &[tree_sitter::Node<'tree>],
+    name: &[u8],
+    arity: usize,
+) -> Vec<(tree_sitter::Node<'tree>, Vec<tree_sitter::Node<'tree>>)> {
+    let mut res = Vec::new();
+    for call in nodes
+        .iter()
+        .filter(|n| n.kind() == "generic_function" && called_name(input, n) == Some(name))
+    {
+        let Some(type_args) = call.child_by_field_name("type_arguments") else {
+            continue;
+        };
+        let mut cursor = type_args.walk();
+        let args = type_args
+            .named_children(&mut cursor)
+            .filter(|a| a.kind() != "lifetime")
+            .collect::<Vec<_>>();
+        if args.len() == arity {
+            res.push((*call, args));
+        }
+    }
+    res
+}
+
+/// Replace function type parameters with the first concrete type seen for them
+///
+/// The concrete type is taken from the first turbofish call site found, by name, in
+/// the file. Uses of the parameter inside the function are replaced with it, and the
+/// corresponding type argument is removed from all the turbofish call sites. This is
+/// best-effort: if the other call sites used different types, the test will most
+/// likely reject the edit.
+///
+/// Only turbofish call sites like `foo::<u8>()` are considered, as finding the type
+/// inferred for `foo(x)` would need type-checking. Functions that are only called
+/// with inferred type arguments are thus left generic.
+pub fn monomorphize_functions(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for function in nodes.iter().filter(|n| n.kind() == "function_item") {
+        let (Some(name), Some(type_params)) = (
+            function.child_by_field_name("name"),
+            function.child_by_field_name("type_parameters"),
+        ) else {
+            continue;
+        };
+        let name = &input[name.byte_range()];
+        let function_nodes = descendants(function);
+        let mut cursor = type_params.walk();
+        let params = type_params
+            .named_children(&mut cursor)
+            .filter(|p| p.kind() != "lifetime")
+            .collect::<Vec<_>>();
+        let calls = turbofish_calls(input, &nodes, name, params.len());
+        for (idx, param) in params.iter().enumerate() {
+            let Some(param_name) = type_param_name(input, param) else {
+                continue;
+            };
+            let Some(concrete) = calls
+                .iter()
+                .map(|(_, args)| &input[args[idx].byte_range()])
+                .find(|ty| *ty != b"_")
+            else {
+                continue;
+            };
+            let mut group = match type_params.named_child_count() {
+                1 => vec![(type_params.byte_range(), Vec::new())],
+                _ => vec![(with_separator(param, ","), Vec::new())],
+            };
+            for usage in function_nodes.iter().filter(|n| {
+                &input[n.byte_range()] == param_name
+                    && !param.byte_range().contains(&n.start_byte())
+            }) {
+                match usage.kind() {
+                    "type_identifier" => group.push((usage.byte_range(), concrete.to_vec())),
+                    // `T::foo()` needs to become `<Concrete>::foo()`
+                    "identifier"
+                        if usage.parent().map_or(false, |p| {
+                            p.kind() == "scoped_identifier"
+                                && p.child_by_field_name("path") == Some(*usage)
+                        }) =>
+                    {
+                        let replacement = [b"<", concrete, b">"].concat();
+                        group.push((usage.byte_range(), replacement));
+                    }
+                    _ => (),
+                }
+            }
+            for (call, args) in calls.iter() {
+                let (Some(called), Some(type_args)) = (
+                    call.child_by_field_name("function"),
+                    call.child_by_field_name("type_arguments"),
+                ) else {
+                    continue;
+                };
+                match type_args.named_child_count() {
+                    1 => group.push((called.end_byte()..call.end_byte(), Vec::new())),
+                    _ => group.push((with_separator(&args[idx], ","), Vec::new())),
+                }
+            }
+            res.push(group);
+        }
+    }
+    res
+}
@@ -0,0 +1,54 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::descendants;
+
+/// Remove the arguments of attributes, either all at once or one at a time
+///
+/// For instance, `#[tokio::main(flavor = "multi_thread")]` can become
+/// `#[tokio::main]`, and `#[derive(Clone, Debug)]` can become `#[derive(Debug)]`.
+/// This is useful when the attribute itself is needed, but not its configuration.
+/// The arguments of the attributes named in `keep_attributes` are left untouched.
+pub fn reduce_attribute_args(
+    input: &[u8],
+    root: &tree_sitter::Node,
+    keep_attributes: &[String],
+) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for args in descendants(root).iter().filter(|n| {
+        n.kind() == "token_tree"
+            && n.parent().map_or(false, |p| {
+                p.kind() == "attribute"
+                    && p.child_by_field_name("arguments") == Some(**n)
+                    && p.named_child(0).map_or(false, |name| {
+                        let name = &input[name.byte_range()];
+                        !keep_attributes.iter().any(|k| k.as_bytes() == name)
+                    })
+            })
+    }) {
+        res.push(vec![(args.byte_range(), Vec::new())]);
+
+        // Split the arguments on the top-level commas, ignoring the delimiters
+        let mut cursor = args.walk();
+        let tokens = args.children(&mut cursor).collect::<Vec<_>>();
+        let Some(tokens) = tokens.get(1..tokens.len().saturating_sub(1)) else {
+            continue;
+        };
+        let elements = tokens
+            .split(|t| t.kind() == ",")
+            .filter(|e| !e.is_empty())
+            .collect::<Vec<_>>();
+        if elements.len() < 2 {
+            continue;
+        }
+        for element in elements {
+            let (first, last) = (element[0], element[element.len() - 1]);
+            let range = match (last.next_sibling(), first.prev_sibling()) {
+                (Some(next), _) if next.kind() == "," => first.start_byte()..next.end_byte(),
+                (_, Some(prev)) if prev.kind() == "," => prev.start_byte()..last.end_byte(),
+                _ => first.start_byte()..last.end_byte(),
+            };
+            res.push(vec![(range, Vec::new())]);
+        }
+    }
+    res
+}
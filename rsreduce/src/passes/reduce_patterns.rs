@@ -0,0 +1,71 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, with_separator};
+
+/// Remove alternatives from or-patterns, and components from tuple and struct patterns
+///
+/// Alternatives are removed by replacing `A | B` with either `A` or `B`. Components
+/// are dropped along with their separator if the pattern has a `..`, so that the
+/// pattern stays valid. Otherwise, they are replaced with `_`.
+pub fn reduce_patterns(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for node in descendants(root) {
+        match node.kind() {
+            "or_pattern" => {
+                let mut cursor = node.walk();
+                for alternative in node.named_children(&mut cursor) {
+                    res.push(vec![(
+                        node.byte_range(),
+                        input[alternative.byte_range()].to_vec(),
+                    )]);
+                }
+            }
+            "tuple_pattern" | "tuple_struct_pattern" => {
+                let has_rest = has_rest(&node);
+                let ty = node.child_by_field_name("type");
+                let mut cursor = node.walk();
+                for element in node.children(&mut cursor) {
+                    let is_element = (element.is_named() || element.kind() == "_")
+                        && Some(element) != ty
+                        && element.kind() != "remaining_field_pattern"
+                        && !element.kind().ends_with("_comment");
+                    if !is_element {
+                        continue;
+                    }
+                    if has_rest {
+                        res.push(vec![(with_separator(&element, ","), Vec::new())]);
+                    } else if element.kind() != "_" {
+                        res.push(vec![(element.byte_range(), b"_".to_vec())]);
+                    }
+                }
+            }
+            "struct_pattern" => {
+                let has_rest = has_rest(&node);
+                let mut cursor = node.walk();
+                for field in node.named_children(&mut cursor) {
+                    if field.kind() != "field_pattern" {
+                        continue;
+                    }
+                    if has_rest {
+                        res.push(vec![(with_separator(&field, ","), Vec::new())]);
+                    } else if let Some(pattern) = field.child_by_field_name("pattern") {
+                        if &input[pattern.byte_range()] != b"_" {
+                            res.push(vec![(pattern.byte_range(), b"_".to_vec())]);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// Whether the tuple or struct pattern `node` has a `..`
+fn has_rest(node: &tree_sitter::Node) -> bool {
+    let mut cursor = node.walk();
+    let res = node
+        .named_children(&mut cursor)
+        .any(|c| c.kind() == "remaining_field_pattern");
+    res
+}
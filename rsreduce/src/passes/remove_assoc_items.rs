@@ -0,0 +1,66 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, trait_name_of};
+
+/// Returns the kind of the item implementing the trait associated item of kind `kind`
+fn impl_kind(kind: &str) -> Option<&'static str> {
+    match kind {
+        "associated_type" => Some("type_item"),
+        "const_item" => Some("const_item"),
+        "function_signature_item" | "function_item" => Some("function_item"),
+        _ => None,
+    }
+}
+
+/// Remove associated items from traits, along with their implementations
+///
+/// Impls are matched with the trait by name, so this is best-effort: if an impl of
+/// another trait with the same name gets edited too, the test will just fail.
+pub fn remove_assoc_items(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for trait_item in nodes.iter().filter(|n| n.kind() == "trait_item") {
+        let (Some(trait_name), Some(body)) = (
+            trait_item.child_by_field_name("name"),
+            trait_item.child_by_field_name("body"),
+        ) else {
+            continue;
+        };
+        let trait_name = &input[trait_name.byte_range()];
+        let impl_bodies = nodes
+            .iter()
+            .filter(|n| n.kind() == "impl_item")
+            .filter(|n| {
+                n.child_by_field_name("trait")
+                    .and_then(|t| trait_name_of(&t))
+                    .map_or(false, |t| &input[t.byte_range()] == trait_name)
+            })
+            .filter_map(|n| n.child_by_field_name("body"))
+            .collect::<Vec<_>>();
+        let mut cursor = body.walk();
+        for item in body.named_children(&mut cursor) {
+            let (Some(kind), Some(name)) =
+                (impl_kind(item.kind()), item.child_by_field_name("name"))
+            else {
+                continue;
+            };
+            let name = &input[name.byte_range()];
+            let mut group = vec![(item.byte_range(), Vec::new())];
+            for impl_body in &impl_bodies {
+                let mut cursor = impl_body.walk();
+                group.extend(
+                    impl_body
+                        .named_children(&mut cursor)
+                        .filter(|i| {
+                            i.kind() == kind
+                                && i.child_by_field_name("name")
+                                    .map_or(false, |n| &input[n.byte_range()] == name)
+                        })
+                        .map(|i| (i.byte_range(), Vec::new())),
+                );
+            }
+            res.push(group);
+        }
+    }
+    res
+}
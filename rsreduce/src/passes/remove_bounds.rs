@@ -0,0 +1,33 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, with_separator};
+
+/// Remove individual bounds from `A + B` bound lists
+///
+/// This handles both bound lists like in `T: A + B` or `type X: A + B;`, and
+/// inline bounds like in `impl A + B` or `dyn A + 'static`. For the latter, only the
+/// bounds after the first one can be removed.
+pub fn remove_bounds(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for node in descendants(root) {
+        match node.kind() {
+            "trait_bounds" if node.named_child_count() > 1 => {
+                let mut cursor = node.walk();
+                for bound in node.named_children(&mut cursor) {
+                    if bound.kind().ends_with("_comment") {
+                        continue;
+                    }
+                    res.push(vec![(with_separator(&bound, "+"), Vec::new())]);
+                }
+            }
+            "bounded_type" => {
+                let Some(left) = node.named_child(0) else {
+                    continue;
+                };
+                res.push(vec![(node.byte_range(), input[left.byte_range()].to_vec())]);
+            }
+            _ => (),
+        }
+    }
+    res
+}
@@ -0,0 +1,121 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{called_name, descendants, with_separator};
+
+/// Items whose const parameters this pass removes
+const GENERIC_ITEMS: &[&str] = &[
+    "function_item",
+    "struct_item",
+    "enum_item",
+    "union_item",
+    "trait_item",
+    "type_item",
+    "impl_item",
+];
+
+/// Returns the name of the type used by `generic_type` node `ty`
+fn used_type_name<'a>(input: &'a [u8], ty: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let name = ty.child_by_field_name("type")?;
+    let name = match name.kind() {
+        "type_identifier" => name,
+        "scoped_type_identifier" => name.child_by_field_name("name")?,
+        _ => return None,
+    };
+    Some(&input[name.byte_range()])
+}
+
+/// Returns a value that a const parameter of type `ty` can be replaced with
+fn default_value(ty: &[u8]) -> &'static [u8] {
+    match ty {
+        b"bool" => b"false",
+        b"char" => b"'a'",
+        _ => b"0",
+    }
+}
+
+/// Remove const generic parameters, along with their uses
+///
+/// Inside the item declaring the parameter, uses of the parameter are replaced with
+/// a constant value. Outside of it, the corresponding argument is removed from all
+/// the turbofish call sites and generic types found, by name, in the file.
+pub fn remove_const_params(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for item in nodes.iter().filter(|n| GENERIC_ITEMS.contains(&n.kind())) {
+        let Some(type_params) = item.child_by_field_name("type_parameters") else {
+            continue;
+        };
+        let name = item.child_by_field_name("name");
+        let item_nodes = descendants(item);
+        let mut cursor = type_params.walk();
+        let params = type_params
+            .named_children(&mut cursor)
+            .filter(|p| p.kind() != "lifetime")
+            .collect::<Vec<_>>();
+        for (idx, param) in params.iter().enumerate() {
+            if param.kind() != "const_parameter" {
+                continue;
+            }
+            let (Some(param_name), Some(param_type)) = (
+                param.child_by_field_name("name"),
+                param.child_by_field_name("type"),
+            ) else {
+                continue;
+            };
+            let param_name = &input[param_name.byte_range()];
+            let value = default_value(&input[param_type.byte_range()]);
+            let mut group = match type_params.named_child_count() {
+                1 => vec![(type_params.byte_range(), Vec::new())],
+                _ => vec![(with_separator(param, ","), Vec::new())],
+            };
+            for usage in item_nodes.iter().filter(|n| {
+                (n.kind() == "identifier" || n.kind() == "type_identifier")
+                    && &input[n.byte_range()] == param_name
+                    && !param.byte_range().contains(&n.start_byte())
+            }) {
+                group.push((usage.byte_range(), value.to_vec()));
+            }
+            let Some(name) = name.map(|n| &input[n.byte_range()]) else {
+                res.push(group);
+                continue;
+            };
+            for usage in nodes.iter() {
+                let used_name = match usage.kind() {
+                    "generic_function" if item.kind() == "function_item" => {
+                        called_name(input, usage)
+                    }
+                    "generic_type" if item.kind() != "function_item" => {
+                        used_type_name(input, usage)
+                    }
+                    _ => None,
+                };
+                if used_name != Some(name) {
+                    continue;
+                }
+                let (Some(used), Some(type_args)) = (
+                    usage
+                        .child_by_field_name("function")
+                        .or_else(|| usage.child_by_field_name("type")),
+                    usage.child_by_field_name("type_arguments"),
+                ) else {
+                    continue;
+                };
+                let mut cursor = type_args.walk();
+                let args = type_args
+                    .named_children(&mut cursor)
+                    .filter(|a| a.kind() != "lifetime")
+                    .collect::<Vec<_>>();
+                if args.len() != params.len() {
+                    // Not the item we're looking for, or a weird use site
+                    continue;
+                }
+                match type_args.named_child_count() {
+                    1 => group.push((used.end_byte()..usage.end_byte(), Vec::new())),
+                    _ => group.push((with_separator(&args[idx], ","), Vec::new())),
+                }
+            }
+            res.push(group);
+        }
+    }
+    res
+}
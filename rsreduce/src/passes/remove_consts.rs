@@ -0,0 +1,40 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::descendants;
+
+/// Remove `const` and `static` items, replacing all their uses with `todo!()`
+///
+/// Uses are found by name, so this is best-effort.
+pub fn remove_consts(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for item in nodes
+        .iter()
+        .filter(|n| n.kind() == "const_item" || n.kind() == "static_item")
+    {
+        let Some(name) = item.child_by_field_name("name") else {
+            continue;
+        };
+        let name = &input[name.byte_range()];
+        let mut group = vec![(item.byte_range(), Vec::new())];
+        for usage in nodes.iter().filter(|n| {
+            n.kind() == "identifier"
+                && &input[n.byte_range()] == name
+                && !item.byte_range().contains(&n.start_byte())
+        }) {
+            // Replace the whole path for uses like `Self::FOO` or `module::FOO`
+            let usage = match usage.parent() {
+                Some(p)
+                    if p.kind() == "scoped_identifier"
+                        && p.child_by_field_name("name") == Some(*usage) =>
+                {
+                    p
+                }
+                _ => *usage,
+            };
+            group.push((usage.byte_range(), b"todo!()".to_vec()));
+        }
+        res.push(group);
+    }
+    res
+}
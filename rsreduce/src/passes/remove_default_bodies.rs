@@ -0,0 +1,63 @@
+use crate::util::{descendants, trait_name_of};
+
+/// Turn `{loop{}}` default bodies of trait methods back into signatures
+///
+/// This is the inverse of the "Add default methods to function signatures" pass, so
+/// it only applies when the default body is redundant: the trait must be
+/// implemented at least once in the same file, and all these implementations must
+/// override the method. Otherwise, the two passes could keep undoing each other.
+pub fn remove_default_bodies(input: &[u8], node: &tree_sitter::Node) -> Option<Vec<u8>> {
+    if node.kind() != "function_item" {
+        return None;
+    }
+    let body = node.child_by_field_name("body")?;
+    let is_loop = input[body.byte_range()]
+        .iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .eq(b"{loop{}}");
+    let trait_item = node.parent()?.parent()?;
+    if !is_loop || trait_item.kind() != "trait_item" {
+        return None;
+    }
+    let trait_name = &input[trait_item.child_by_field_name("name")?.byte_range()];
+    let method_name = &input[node.child_by_field_name("name")?.byte_range()];
+
+    let mut root = trait_item;
+    while let Some(parent) = root.parent() {
+        root = parent;
+    }
+    let impls = descendants(&root)
+        .into_iter()
+        .filter(|n| n.kind() == "impl_item")
+        .filter(|n| {
+            n.child_by_field_name("trait")
+                .and_then(|t| trait_name_of(&t))
+                .map_or(false, |t| &input[t.byte_range()] == trait_name)
+        })
+        .collect::<Vec<_>>();
+    let all_override = impls.iter().all(|i| {
+        let Some(impl_body) = i.child_by_field_name("body") else {
+            return false;
+        };
+        let mut cursor = impl_body.walk();
+        let res = impl_body.named_children(&mut cursor).any(|item| {
+            item.kind() == "function_item"
+                && item
+                    .child_by_field_name("name")
+                    .map_or(false, |n| &input[n.byte_range()] == method_name)
+        });
+        res
+    });
+    if impls.is_empty() || !all_override {
+        return None;
+    }
+
+    let signature = &input[node.start_byte()..body.start_byte()];
+    let end = signature
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    let mut signature = signature[..end].to_vec();
+    signature.push(b';');
+    Some(signature)
+}
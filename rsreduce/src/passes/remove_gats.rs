@@ -0,0 +1,187 @@
+use std::ops::Range;
+
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, trait_name_of, type_param_name, with_separator};
+
+/// Returns the byte range of the `where` clause of associated type `item`, if any
+///
+/// The grammar does not know about `where` clauses on associated types, so they end
+/// up in `ERROR` nodes and are found by their `where` token instead. The range ends
+/// before the `=` following the clause if any, and before the final `;` otherwise.
+fn where_range(item: &tree_sitter::Node) -> Option<Range<usize>> {
+    let nodes = descendants(item);
+    let start = nodes.iter().find(|n| n.kind() == "where")?.start_byte();
+    let is_top_level = |n: &tree_sitter::Node| {
+        n.parent().map_or(false, |p| {
+            p == *item || (p.kind() == "ERROR" && p.parent() == Some(*item))
+        })
+    };
+    let end = nodes
+        .iter()
+        .find(|n| n.start_byte() > start && n.kind() == "=" && is_top_level(n))
+        .or_else(|| nodes.iter().rfind(|n| n.kind() == ";" && is_top_level(n)))
+        .map_or(item.end_byte(), |n| n.start_byte());
+    Some(start..end)
+}
+
+/// Returns the edits removing the generic parameters of associated type `item`
+///
+/// The `where` clause is removed too, and the uses of the parameters get replaced
+/// with `'static` for lifetimes and `()` for types.
+fn remove_generics(input: &[u8], item: &tree_sitter::Node) -> Vec<(Range<usize>, Vec<u8>)> {
+    let Some(params) = item.child_by_field_name("type_parameters") else {
+        return Vec::new();
+    };
+    let mut cursor = params.walk();
+    let names = params
+        .named_children(&mut cursor)
+        .filter_map(|p| match p.kind() {
+            "lifetime" => Some(&input[p.byte_range()]),
+            _ => type_param_name(input, &p),
+        })
+        .collect::<Vec<_>>();
+    let mut res = vec![(params.byte_range(), Vec::new())];
+    res.extend(where_range(item).map(|r| (r, Vec::new())));
+    for node in descendants(item) {
+        let replacement: &[u8] = match node.kind() {
+            "lifetime" => b"'static",
+            "type_identifier" => b"()",
+            _ => continue,
+        };
+        if names.contains(&&input[node.byte_range()]) {
+            res.push((node.byte_range(), replacement.to_vec()));
+        }
+    }
+    res
+}
+
+/// Sorts `edits`, dropping the ones nested in previous ones
+///
+/// Removals partially overlapping the previous ones, like two neighboring list
+/// elements that each come with a separator, are shortened to not overlap.
+fn without_nested(mut edits: Vec<(Range<usize>, Vec<u8>)>) -> Vec<(Range<usize>, Vec<u8>)> {
+    edits.sort_by_key(|(r, _)| (r.start, std::cmp::Reverse(r.end)));
+    let mut end = 0;
+    edits.retain_mut(|(r, _)| {
+        if r.end <= end {
+            return false;
+        }
+        r.start = r.start.max(end);
+        end = r.end;
+        true
+    });
+    edits
+}
+
+/// Remove generic associated types, or make them non-generic
+///
+/// For each GAT declared in a trait, two edits are tried. The first one removes
+/// the declaration, its definitions in the impls of the trait, and the bounds on
+/// it. The second one removes the generic parameters of the declaration and the
+/// definitions, along with the generic arguments of its uses. Impls and uses are
+/// matched by name, so the test rejects the edits that hit another trait's GAT.
+pub fn remove_gats(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for trait_item in nodes.iter().filter(|n| n.kind() == "trait_item") {
+        let (Some(trait_name), Some(body)) = (
+            trait_item.child_by_field_name("name"),
+            trait_item.child_by_field_name("body"),
+        ) else {
+            continue;
+        };
+        let trait_name = &input[trait_name.byte_range()];
+        let is_trait = |t: Option<tree_sitter::Node>| {
+            t.and_then(|t| trait_name_of(&t))
+                .map_or(false, |t| &input[t.byte_range()] == trait_name)
+        };
+        let impl_bodies = nodes
+            .iter()
+            .filter(|n| n.kind() == "impl_item" && is_trait(n.child_by_field_name("trait")))
+            .filter_map(|n| n.child_by_field_name("body"))
+            .collect::<Vec<_>>();
+        let mut cursor = body.walk();
+        for decl in body.named_children(&mut cursor).filter(|i| {
+            i.kind() == "associated_type" && i.child_by_field_name("type_parameters").is_some()
+        }) {
+            let Some(name) = decl.child_by_field_name("name") else {
+                continue;
+            };
+            let name = &input[name.byte_range()];
+            let is_named = |n: &tree_sitter::Node| {
+                n.child_by_field_name("name")
+                    .map_or(false, |n| &input[n.byte_range()] == name)
+            };
+            // Definitions with a `where` clause parse as declarations
+            let mut defs = vec![decl];
+            for impl_body in &impl_bodies {
+                let mut cursor = impl_body.walk();
+                defs.extend(impl_body.named_children(&mut cursor).filter(|i| {
+                    matches!(i.kind(), "type_item" | "associated_type") && is_named(i)
+                }));
+            }
+            let outside_defs = |n: &tree_sitter::Node| {
+                !defs
+                    .iter()
+                    .any(|d| d.byte_range().contains(&n.start_byte()))
+            };
+            // `T::Name<..>` types, and `Trait<Name<..> = ..>` bindings
+            let uses = nodes
+                .iter()
+                .filter(|n| {
+                    n.kind() == "generic_type"
+                        && n.child_by_field_name("type")
+                            .filter(|t| t.kind() == "scoped_type_identifier")
+                            .map_or(false, |t| is_named(&t))
+                        && outside_defs(n)
+                })
+                .collect::<Vec<_>>();
+            let bindings = nodes
+                .iter()
+                .filter(|n| {
+                    n.kind() == "type_binding"
+                        && is_named(n)
+                        && n.parent()
+                            .and_then(|args| args.parent())
+                            .map_or(false, |t| is_trait(t.child_by_field_name("type")))
+                        && outside_defs(n)
+                })
+                .collect::<Vec<_>>();
+
+            let mut removal = defs
+                .iter()
+                .map(|d| (d.byte_range(), Vec::new()))
+                .collect::<Vec<_>>();
+            for ty in &uses {
+                let Some(mut bounded) = ty.parent() else {
+                    continue;
+                };
+                if bounded.kind() == "higher_ranked_trait_bound" {
+                    bounded = bounded.parent().unwrap();
+                }
+                if bounded.kind() == "where_predicate" {
+                    removal.push((with_separator(&bounded, ","), Vec::new()));
+                }
+            }
+            for binding in &bindings {
+                removal.push((with_separator(binding, ","), Vec::new()));
+            }
+            res.push(without_nested(removal));
+
+            let mut simplification = Vec::new();
+            for def in &defs {
+                simplification.extend(remove_generics(input, def));
+            }
+            for args in uses
+                .iter()
+                .chain(bindings.iter())
+                .filter_map(|n| n.child_by_field_name("type_arguments"))
+            {
+                simplification.push((args.byte_range(), Vec::new()));
+            }
+            res.push(without_nested(simplification));
+        }
+    }
+    res
+}
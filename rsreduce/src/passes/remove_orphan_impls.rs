@@ -0,0 +1,197 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::Context;
+use tree_sitter_reduce::{
+    passes::generic::{EditGroup, TreeSitterGroupReplace},
+    Job, JobStatus, Pass, Test,
+};
+
+use crate::util::{descendants, trait_name_of, type_param_name};
+
+/// Returns the name of the type implemented by `impl_item` node `item`
+///
+/// Generic arguments and references are skipped, so that this is `Foo` for both
+/// `impl Foo<T>` and `impl Trait for &Foo`. Type parameters of the impl are ignored.
+fn impl_type_name<'a>(input: &'a [u8], item: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let mut ty = item.child_by_field_name("type")?;
+    while ty.kind() == "reference_type" {
+        ty = ty.child_by_field_name("type")?;
+    }
+    let name = trait_name_of(&ty)?;
+    let name = &input[name.byte_range()];
+    if let Some(params) = item.child_by_field_name("type_parameters") {
+        let mut cursor = params.walk();
+        let is_param = params
+            .named_children(&mut cursor)
+            .any(|p| type_param_name(input, &p) == Some(name));
+        if is_param {
+            return None;
+        }
+    }
+    Some(name)
+}
+
+/// Returns the name of the trait implemented by `impl_item` node `item`, if any
+fn impl_trait_name<'a>(input: &'a [u8], item: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let name = trait_name_of(&item.child_by_field_name("trait")?)?;
+    Some(&input[name.byte_range()])
+}
+
+/// Types and traits defined in a set of files
+#[derive(Default)]
+struct Definitions {
+    types: HashSet<Vec<u8>>,
+    traits: HashSet<Vec<u8>>,
+}
+
+impl Definitions {
+    fn add(&mut self, input: &[u8], root: &tree_sitter::Node) {
+        for node in descendants(root) {
+            let set = match node.kind() {
+                "struct_item" | "enum_item" | "union_item" | "type_item" => &mut self.types,
+                "trait_item" => &mut self.traits,
+                _ => continue,
+            };
+            if let Some(name) = node.child_by_field_name("name") {
+                set.insert(input[name.byte_range()].to_vec());
+            }
+        }
+    }
+
+    /// Whether the impl `item` is for a type that must be defined in the tree, but
+    /// is not
+    ///
+    /// Inherent impls must be for a type defined in the crate, and so must trait
+    /// impls of traits defined outside of it. Trait impls of traits defined in the
+    /// crate could be for types from other crates, so they are never orphans.
+    fn is_orphan(&self, input: &[u8], item: &tree_sitter::Node) -> bool {
+        let Some(ty) = impl_type_name(input, item) else {
+            return false;
+        };
+        let local_trait = impl_trait_name(input, item).map_or(false, |t| self.traits.contains(t));
+        !self.types.contains(ty) && !local_trait
+    }
+}
+
+fn parse(input: &[u8]) -> Option<tree_sitter::Tree> {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(tree_sitter_rust::language())
+        .expect("Failed to make a parser with configured language");
+    parser.parse(input, None)
+}
+
+/// Remove the impls of types that are not defined anywhere any longer
+///
+/// This usually happens after the type itself got removed. Types and traits are
+/// looked up by name in all the rust files of the tree, so this can miss types that
+/// are defined by macros, in which case the test will reject the edit.
+#[derive(Debug, Hash)]
+pub struct RemoveOrphanImpls;
+
+impl RemoveOrphanImpls {
+    /// Returns the definitions from the tree that could make an impl of `file` not
+    /// an orphan, or `None` if `file` has no candidate orphan impl
+    fn definitions(&self, workdir: &Path, file: &Path) -> anyhow::Result<Option<Definitions>> {
+        let input = std::fs::read(file).with_context(|| format!("reading file {file:?}"))?;
+        let Some(tree) = parse(&input) else {
+            return Ok(None);
+        };
+        let mut local = Definitions::default();
+        local.add(&input, &tree.root_node());
+        let mut names = Vec::new();
+        for item in descendants(&tree.root_node())
+            .iter()
+            .filter(|n| n.kind() == "impl_item" && local.is_orphan(&input, n))
+        {
+            names.extend(impl_type_name(&input, item));
+            names.extend(impl_trait_name(&input, item));
+        }
+        if names.is_empty() {
+            return Ok(None);
+        }
+
+        // Only parse the files that could define one of the names, skipping the
+        // build artifacts that can be much larger than the sources
+        let mut res = local;
+        for entry in walkdir::WalkDir::new(workdir)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != "target")
+        {
+            let entry = entry.with_context(|| format!("walking directory {workdir:?}"))?;
+            let path = entry.path();
+            let is_rust =
+                entry.file_type().is_file() && entry.file_name().to_string_lossy().ends_with(".rs");
+            if !is_rust || path == file {
+                continue;
+            }
+            let contents = std::fs::read(path).with_context(|| format!("reading file {path:?}"))?;
+            let mentions_name = names
+                .iter()
+                .any(|n| contents.windows(n.len()).any(|w| w == *n));
+            if !mentions_name {
+                continue;
+            }
+            if let Some(tree) = parse(&contents) {
+                res.add(&contents, &tree.root_node());
+            }
+        }
+        Ok(Some(res))
+    }
+
+    /// The pass actually removing the orphan impls, given the tree's `definitions`
+    fn pass(&self, definitions: Definitions) -> impl Pass {
+        TreeSitterGroupReplace {
+            language: tree_sitter_rust::language(),
+            extensions: &["rs"],
+            name: String::from("Remove impls of missing types"),
+            group_finder: move |input: &[u8], root: &tree_sitter::Node| {
+                descendants(root)
+                    .into_iter()
+                    .filter(|n| n.kind() == "impl_item" && definitions.is_orphan(input, n))
+                    .map(|n| vec![(n.byte_range(), Vec::new())])
+                    .collect::<Vec<EditGroup>>()
+            },
+        }
+    }
+}
+
+impl Pass for RemoveOrphanImpls {
+    fn reduce(
+        &self,
+        workdir: &Path,
+        test: &dyn Test,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        let Some(definitions) = self.definitions(workdir, &workdir.join(&job.path))? else {
+            return Ok(JobStatus::PassFailed(String::from(
+                "No impl of a missing type to remove",
+            )));
+        };
+        self.pass(definitions)
+            .reduce(workdir, test, job, kill_trigger)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rs"]
+    }
+
+    fn language(&self) -> Option<tree_sitter::Language> {
+        Some(tree_sitter_rust::language())
+    }
+
+    fn attempt_sizes(
+        &self,
+        workdir: &Path,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<Option<Vec<usize>>> {
+        match self.definitions(workdir, &workdir.join(&job.path))? {
+            None => Ok(Some(Vec::new())),
+            Some(definitions) => self
+                .pass(definitions)
+                .attempt_sizes(workdir, job, kill_trigger),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, with_separator};
+
+/// Remove explicit fields from struct expressions that end with `..base`
+///
+/// For instance, `S { a: 1, b, ..Default::default() }` can become
+/// `S { b, ..Default::default() }`, which stays valid as the removed fields are
+/// then taken from the base. This is most useful when the explicit value is the
+/// same as the default one, which is checked by the test.
+pub fn remove_overridden_fields(_input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for node in descendants(root) {
+        if node.kind() != "field_initializer_list" {
+            continue;
+        }
+        let mut cursor = node.walk();
+        let fields = node.named_children(&mut cursor).collect::<Vec<_>>();
+        if !fields.iter().any(|f| f.kind() == "base_field_initializer") {
+            continue;
+        }
+        for field in fields {
+            if matches!(
+                field.kind(),
+                "field_initializer" | "shorthand_field_initializer"
+            ) {
+                res.push(vec![(with_separator(&field, ","), Vec::new())]);
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, trait_name_of, type_param_name, with_separator};
+
+/// Returns the last segment of the name of the struct built or matched by `node`, a
+/// `struct_expression` or `struct_pattern`
+fn struct_name<'a>(input: &'a [u8], node: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let ty = match node.kind() {
+        "struct_expression" => node.child_by_field_name("name")?,
+        "struct_pattern" => node.child_by_field_name("type")?,
+        _ => return None,
+    };
+    let name = match ty.kind() {
+        "scoped_type_identifier" | "scoped_identifier" => ty.child_by_field_name("name")?,
+        "generic_type_with_turbofish" => ty.child_by_field_name("type")?,
+        _ => ty,
+    };
+    Some(&input[name.byte_range()])
+}
+
+/// Returns the byte ranges of the initializers and patterns of field `field` of the
+/// struct `name`, each with one adjacent `,`
+///
+/// `Self { .. }` is assumed to refer to the struct, as there is no way to know
+/// without resolving names.
+fn field_uses(
+    input: &[u8],
+    nodes: &[tree_sitter::Node],
+    name: &[u8],
+    field: &[u8],
+) -> Vec<Range<usize>> {
+    let mut res = Vec::new();
+    for node in nodes
+        .iter()
+        .filter(|n| struct_name(input, n).map_or(false, |n| n == name || n == b"Self"))
+    {
+        let list = match node.kind() {
+            "struct_expression" => match node.child_by_field_name("body") {
+                Some(body) => body,
+                None => continue,
+            },
+            _ => *node,
+        };
+        let mut cursor = list.walk();
+        for element in list.named_children(&mut cursor) {
+            let element_name = match element.kind() {
+                "field_initializer" | "field_pattern" => element.child_by_field_name("name"),
+                "shorthand_field_initializer" => element.named_child(0),
+                _ => None,
+            };
+            if element_name.map_or(false, |n| &input[n.byte_range()] == field) {
+                res.push(with_separator(&element, ","));
+            }
+        }
+    }
+    res
+}
+
+/// Returns the byte range removing the `idx`-th non-lifetime element of `list`, a
+/// `type_parameters` or `type_arguments`, or the whole list if it has one element
+fn remove_from_list(list: &tree_sitter::Node, idx: usize) -> Option<Range<usize>> {
+    let mut cursor = list.walk();
+    let element = list
+        .named_children(&mut cursor)
+        .filter(|p| p.kind() != "lifetime")
+        .nth(idx)?;
+    Some(match list.named_child_count() {
+        1 => list.byte_range(),
+        _ => with_separator(&element, ","),
+    })
+}
+
+/// Returns the edits removing the `idx`-th type parameter of struct `name` from all
+/// the `name<..>` types, and from the parameters of the impls of `name`
+fn remove_type_arg(
+    input: &[u8],
+    nodes: &[tree_sitter::Node],
+    name: &[u8],
+    idx: usize,
+) -> Vec<Range<usize>> {
+    let mut res = Vec::new();
+    for ty in nodes.iter().filter(|n| {
+        n.kind() == "generic_type"
+            && n.child_by_field_name("type")
+                .and_then(|t| trait_name_of(&t))
+                .map_or(false, |t| &input[t.byte_range()] == name)
+    }) {
+        let Some(args) = ty.child_by_field_name("type_arguments") else {
+            continue;
+        };
+        let Some(range) = remove_from_list(&args, idx) else {
+            continue;
+        };
+        res.push(range);
+
+        // `impl<T> Foo<T>` must also lose its `T`, that is no longer constrained
+        let Some(item) = ty
+            .parent()
+            .filter(|p| p.kind() == "impl_item" && p.child_by_field_name("type") == Some(*ty))
+        else {
+            continue;
+        };
+        let Some(impl_params) = item.child_by_field_name("type_parameters") else {
+            continue;
+        };
+        let mut cursor = args.walk();
+        let Some(arg) = args
+            .named_children(&mut cursor)
+            .filter(|a| a.kind() != "lifetime")
+            .nth(idx)
+        else {
+            continue;
+        };
+        let arg = &input[arg.byte_range()];
+        let mut cursor = impl_params.walk();
+        let param_idx = impl_params
+            .named_children(&mut cursor)
+            .filter(|p| p.kind() != "lifetime")
+            .position(|p| type_param_name(input, &p) == Some(arg));
+        if let Some(range) = param_idx.and_then(|i| remove_from_list(&impl_params, i)) {
+            res.push(range);
+        }
+    }
+    res
+}
+
+/// Remove the `PhantomData` fields of structs
+///
+/// The field is removed along with its initializers in struct expressions and its
+/// mentions in struct patterns. When the field was the only one using some of the
+/// struct's type parameters, removing these type parameters from the struct, its
+/// uses and its impls is also tried, as they are now unused.
+pub fn remove_phantom_fields(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for item in nodes.iter().filter(|n| n.kind() == "struct_item") {
+        let (Some(name), Some(body)) = (
+            item.child_by_field_name("name"),
+            item.child_by_field_name("body"),
+        ) else {
+            continue;
+        };
+        if body.kind() != "field_declaration_list" {
+            continue;
+        }
+        let name = &input[name.byte_range()];
+        let mut cursor = body.walk();
+        let fields = body
+            .named_children(&mut cursor)
+            .filter(|f| f.kind() == "field_declaration")
+            .collect::<Vec<_>>();
+        for field in fields.iter() {
+            let (Some(field_name), Some(ty)) = (
+                field.child_by_field_name("name"),
+                field.child_by_field_name("type"),
+            ) else {
+                continue;
+            };
+            let is_phantom = ty.kind() == "generic_type"
+                && ty
+                    .child_by_field_name("type")
+                    .and_then(|t| trait_name_of(&t))
+                    .map_or(false, |t| &input[t.byte_range()] == b"PhantomData");
+            if !is_phantom {
+                continue;
+            }
+            let mut group = vec![(with_separator(field, ","), Vec::new())];
+            for range in field_uses(input, &nodes, name, &input[field_name.byte_range()]) {
+                group.push((range, Vec::new()));
+            }
+            res.push(group.clone());
+
+            // Also remove the type parameters that only this field used
+            let Some(type_params) = item.child_by_field_name("type_parameters") else {
+                continue;
+            };
+            let mut cursor = type_params.walk();
+            let params = type_params
+                .named_children(&mut cursor)
+                .filter(|p| p.kind() != "lifetime")
+                .collect::<Vec<_>>();
+            let uses = |node: &tree_sitter::Node, param: &[u8]| {
+                descendants(node)
+                    .iter()
+                    .any(|n| n.kind() == "type_identifier" && &input[n.byte_range()] == param)
+            };
+            for (idx, param) in params.iter().enumerate() {
+                let Some(param_name) = type_param_name(input, param) else {
+                    continue;
+                };
+                let only_used_here = uses(&ty, param_name)
+                    && !fields.iter().any(|f| f != field && uses(f, param_name));
+                let Some(range) = remove_from_list(&type_params, idx).filter(|_| only_used_here)
+                else {
+                    continue;
+                };
+                let mut group = group.clone();
+                group.push((range, Vec::new()));
+                for range in remove_type_arg(input, &nodes, name, idx) {
+                    group.push((range, Vec::new()));
+                }
+                res.push(group);
+            }
+        }
+    }
+    res
+}
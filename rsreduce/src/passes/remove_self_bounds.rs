@@ -0,0 +1,37 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{any_descendant, descendants, with_separator};
+
+/// Remove the `where` predicates that mention `Self`, one at a time
+///
+/// For instance, `where Self: Sized, T: Clone` can become `where T: Clone`. The
+/// `where` keyword is removed along with the last predicate, as well as when it is
+/// left without any predicate after removing all of them at once.
+pub fn remove_self_bounds(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mentions_self = |p: &tree_sitter::Node| {
+        any_descendant(p, &|d| {
+            d.kind() == "type_identifier" && &input[d.byte_range()] == b"Self"
+        })
+    };
+    let mut res = Vec::new();
+    for clause in descendants(root) {
+        if clause.kind() != "where_clause" {
+            continue;
+        }
+        let mut cursor = clause.walk();
+        let predicates = clause
+            .named_children(&mut cursor)
+            .filter(|p| p.kind() == "where_predicate")
+            .collect::<Vec<_>>();
+        match predicates.as_slice() {
+            [] => res.push(vec![(clause.byte_range(), Vec::new())]),
+            [p] if mentions_self(p) => res.push(vec![(clause.byte_range(), Vec::new())]),
+            _ => {
+                for p in predicates.iter().filter(|p| mentions_self(p)) {
+                    res.push(vec![(with_separator(p, ","), Vec::new())]);
+                }
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,117 @@
+use std::ops::Range;
+
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{called_name, descendants, with_separator};
+
+/// Returns the last segment of the path in `type` field of tuple struct pattern `pat`
+fn pattern_name<'a>(input: &'a [u8], pat: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let ty = pat.child_by_field_name("type")?;
+    let name = match ty.kind() {
+        "identifier" => ty,
+        "scoped_identifier" => ty.child_by_field_name("name")?,
+        _ => return None,
+    };
+    Some(&input[name.byte_range()])
+}
+
+/// Returns the elements of tuple struct pattern `pat`, or `None` if it has a `..`
+fn pattern_elements<'tree>(
+    pat: &tree_sitter::Node<'tree>,
+) -> Option<Vec<tree_sitter::Node<'tree>>> {
+    let ty = pat.child_by_field_name("type");
+    let mut res = Vec::new();
+    let mut cursor = pat.walk();
+    for element in pat.children(&mut cursor) {
+        if element.kind() == "remaining_field_pattern" {
+            return None;
+        }
+        let is_element = (element.is_named() || element.kind() == "_")
+            && Some(element) != ty
+            && !element.kind().ends_with("_comment");
+        if is_element {
+            res.push(element);
+        }
+    }
+    Some(res)
+}
+
+/// Returns the byte range of field `ty` of an `ordered_field_declaration_list`
+///
+/// This includes the visibility and attributes preceding the field's type, and one
+/// adjacent `,`.
+fn field_range(ty: &tree_sitter::Node) -> Range<usize> {
+    let mut first = *ty;
+    while let Some(prev) = first
+        .prev_sibling()
+        .filter(|p| matches!(p.kind(), "visibility_modifier" | "attribute_item"))
+    {
+        first = prev;
+    }
+    if let Some(next) = ty.next_sibling().filter(|n| n.kind() == ",") {
+        return first.start_byte()..next.end_byte();
+    }
+    if let Some(prev) = first.prev_sibling().filter(|n| n.kind() == ",") {
+        return prev.start_byte()..ty.end_byte();
+    }
+    first.start_byte()..ty.end_byte()
+}
+
+/// Remove fields of tuple structs and tuple enum variants
+///
+/// The field is removed from the declaration, along with the corresponding argument
+/// of all the constructor calls and the corresponding element of all the patterns
+/// found, by name, in the file. Uses with the wrong arity, or patterns with a `..`,
+/// are left alone, in which case the test will most likely reject the edit.
+pub fn remove_tuple_fields(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for fields in nodes
+        .iter()
+        .filter(|n| n.kind() == "ordered_field_declaration_list")
+    {
+        let Some(name) = fields
+            .parent()
+            .filter(|p| matches!(p.kind(), "struct_item" | "enum_variant"))
+            .and_then(|p| p.child_by_field_name("name"))
+        else {
+            continue;
+        };
+        let name = &input[name.byte_range()];
+        let mut cursor = fields.walk();
+        let types = fields
+            .children_by_field_name("type", &mut cursor)
+            .collect::<Vec<_>>();
+        for (idx, ty) in types.iter().enumerate() {
+            let mut group = vec![(field_range(ty), Vec::new())];
+            for usage in nodes.iter() {
+                match usage.kind() {
+                    "call_expression" if called_name(input, usage) == Some(name) => {
+                        let Some(args) = usage.child_by_field_name("arguments") else {
+                            continue;
+                        };
+                        let mut cursor = args.walk();
+                        let args = args
+                            .named_children(&mut cursor)
+                            .filter(|a| !a.kind().ends_with("_comment"))
+                            .collect::<Vec<_>>();
+                        if args.len() == types.len() {
+                            group.push((with_separator(&args[idx], ","), Vec::new()));
+                        }
+                    }
+                    "tuple_struct_pattern" if pattern_name(input, usage) == Some(name) => {
+                        let Some(elements) = pattern_elements(usage) else {
+                            continue;
+                        };
+                        if elements.len() == types.len() {
+                            group.push((with_separator(&elements[idx], ","), Vec::new()));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            res.push(group);
+        }
+    }
+    res
+}
@@ -0,0 +1,39 @@
+/// Remove type annotations that type inference may be able to figure out
+///
+/// This handles the types of `let` bindings and closure parameters along with their
+/// `:`, the return types of closures along with their `->`, and the turbofish of
+/// generic function and method calls.
+pub fn remove_type_annotations(input: &[u8], node: &tree_sitter::Node) -> Option<Vec<u8>> {
+    match node.kind() {
+        "let_declaration" => {
+            let pattern = node.child_by_field_name("pattern")?;
+            let ty = node.child_by_field_name("type")?;
+            Some(without(input, node, pattern.end_byte()..ty.end_byte()))
+        }
+        "parameter" if node.parent()?.kind() == "closure_parameters" => {
+            let pattern = node.child_by_field_name("pattern")?;
+            Some(input[pattern.byte_range()].to_vec())
+        }
+        "closure_expression" => {
+            let parameters = node.child_by_field_name("parameters")?;
+            let return_type = node.child_by_field_name("return_type")?;
+            Some(without(
+                input,
+                node,
+                parameters.end_byte()..return_type.end_byte(),
+            ))
+        }
+        "generic_function" => {
+            let function = node.child_by_field_name("function")?;
+            Some(input[function.byte_range()].to_vec())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the text of `node`, without the bytes in `range`
+fn without(input: &[u8], node: &tree_sitter::Node, range: std::ops::Range<usize>) -> Vec<u8> {
+    let mut res = Vec::from(&input[node.start_byte()..range.start]);
+    res.extend_from_slice(&input[range.end..node.end_byte()]);
+    res
+}
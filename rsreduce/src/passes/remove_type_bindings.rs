@@ -0,0 +1,28 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, with_separator};
+
+/// Remove associated type equalities, like the `Item = u8` in `Iterator<Item = u8>`
+///
+/// If the equality is the only generic argument, the whole `<...>` is removed.
+pub fn remove_type_bindings(_input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for node in descendants(root) {
+        if node.kind() != "type_binding" {
+            continue;
+        }
+        let Some(args) = node.parent().filter(|p| p.kind() == "type_arguments") else {
+            continue;
+        };
+        let mut cursor = args.walk();
+        let arg_count = args
+            .named_children(&mut cursor)
+            .filter(|a| !a.kind().ends_with("_comment"))
+            .count();
+        match arg_count {
+            1 => res.push(vec![(args.byte_range(), Vec::new())]),
+            _ => res.push(vec![(with_separator(&node, ","), Vec::new())]),
+        }
+    }
+    res
+}
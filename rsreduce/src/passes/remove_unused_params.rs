@@ -0,0 +1,107 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{any_descendant, called_name, descendants, with_separator};
+
+/// Returns the arguments passed to the function `name` by all its calls in `nodes`
+///
+/// `arity` is the number of parameters of the function, not counting `self`. For
+/// methods, the arguments of `Type::name(receiver, ..)` calls are returned without
+/// the receiver, so that they line up with the ones of `receiver.name(..)` calls.
+/// Calls with another number of arguments are ignored, as they are most likely
+/// calls to another function with the same name.
+fn call_arguments<'tree>(
+    input: &[u8],
+    nodes: &[tree_sitter::Node<'tree>],
+    name: &[u8],
+    arity: usize,
+    has_self: bool,
+) -> Vec<Vec<tree_sitter::Node<'tree>>> {
+    let mut res = Vec::new();
+    for call in nodes
+        .iter()
+        .filter(|n| n.kind() == "call_expression" && called_name(input, n) == Some(name))
+    {
+        let (Some(function), Some(args)) = (
+            call.child_by_field_name("function"),
+            call.child_by_field_name("arguments"),
+        ) else {
+            continue;
+        };
+        let is_method_call = function.kind() == "field_expression";
+        if is_method_call && !has_self {
+            continue;
+        }
+        let mut cursor = args.walk();
+        let mut args = args
+            .named_children(&mut cursor)
+            .filter(|a| !a.kind().ends_with("_comment") && a.kind() != "attribute_item")
+            .collect::<Vec<_>>();
+        if has_self && !is_method_call {
+            if args.is_empty() {
+                continue;
+            }
+            args.remove(0);
+        }
+        if args.len() == arity {
+            res.push(args);
+        }
+    }
+    res
+}
+
+/// Rename unused function parameters to `_name`, or remove them and their arguments
+///
+/// Both edits are tried independently, so that a parameter that cannot be removed,
+/// for instance because the function is used as a callback, can at least be marked
+/// as unused. The arguments are removed from the calls found by name in the same
+/// file, so the test will reject the removal of parameters of functions called from
+/// other files.
+pub fn remove_unused_params(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for function in nodes.iter().filter(|n| n.kind() == "function_item") {
+        let (Some(name), Some(params), Some(body)) = (
+            function.child_by_field_name("name"),
+            function.child_by_field_name("parameters"),
+            function.child_by_field_name("body"),
+        ) else {
+            continue;
+        };
+        let name = &input[name.byte_range()];
+        let mut cursor = params.walk();
+        let params = params
+            .named_children(&mut cursor)
+            .filter(|p| !p.kind().ends_with("_comment") && p.kind() != "attribute_item")
+            .collect::<Vec<_>>();
+        let has_self = params.iter().any(|p| p.kind() == "self_parameter");
+        let params = params
+            .into_iter()
+            .filter(|p| p.kind() != "self_parameter")
+            .collect::<Vec<_>>();
+        let calls = call_arguments(input, &nodes, name, params.len(), has_self);
+        for (idx, param) in params.iter().enumerate() {
+            let Some(pattern) = param
+                .child_by_field_name("pattern")
+                .filter(|p| matches!(p.kind(), "identifier" | "_"))
+            else {
+                continue;
+            };
+            let param_name = &input[pattern.byte_range()];
+            let is_used = any_descendant(&body, &|n| {
+                n.kind() == "identifier" && &input[n.byte_range()] == param_name
+            });
+            if is_used {
+                continue;
+            }
+            if !param_name.starts_with(b"_") {
+                res.push(vec![(pattern.byte_range(), [b"_", param_name].concat())]);
+            }
+            let mut group = vec![(with_separator(param, ","), Vec::new())];
+            for args in calls.iter() {
+                group.push((with_separator(&args[idx], ","), Vec::new()));
+            }
+            res.push(group);
+        }
+    }
+    res
+}
@@ -0,0 +1,70 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{called_name, descendants, type_param_name, with_separator};
+
+/// Remove function type parameters that are not used anywhere in the function
+///
+/// This also removes the corresponding type argument from all turbofish call sites
+/// found, by name, in the file.
+pub fn remove_unused_type_params(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut res = Vec::new();
+    for function in nodes.iter().filter(|n| n.kind() == "function_item") {
+        let (Some(name), Some(type_params)) = (
+            function.child_by_field_name("name"),
+            function.child_by_field_name("type_parameters"),
+        ) else {
+            continue;
+        };
+        let name = &input[name.byte_range()];
+        let function_nodes = descendants(function);
+        let mut cursor = type_params.walk();
+        let params = type_params
+            .named_children(&mut cursor)
+            .filter(|p| p.kind() != "lifetime")
+            .collect::<Vec<_>>();
+        for (idx, param) in params.iter().enumerate() {
+            let Some(param_name) = type_param_name(input, param) else {
+                continue;
+            };
+            let used = function_nodes.iter().any(|n| {
+                n.kind() == "type_identifier"
+                    && &input[n.byte_range()] == param_name
+                    && !param.byte_range().contains(&n.start_byte())
+            });
+            if used {
+                continue;
+            }
+            let mut group = match type_params.named_child_count() {
+                1 => vec![(type_params.byte_range(), Vec::new())],
+                _ => vec![(with_separator(param, ","), Vec::new())],
+            };
+            for call in nodes
+                .iter()
+                .filter(|n| n.kind() == "generic_function" && called_name(input, n) == Some(name))
+            {
+                let (Some(function), Some(type_args)) = (
+                    call.child_by_field_name("function"),
+                    call.child_by_field_name("type_arguments"),
+                ) else {
+                    continue;
+                };
+                let mut cursor = type_args.walk();
+                let args = type_args
+                    .named_children(&mut cursor)
+                    .filter(|a| a.kind() != "lifetime")
+                    .collect::<Vec<_>>();
+                if args.len() != params.len() {
+                    // Not the function we're looking for, or a weird call site
+                    continue;
+                }
+                match type_args.named_child_count() {
+                    1 => group.push((function.end_byte()..call.end_byte(), Vec::new())),
+                    _ => group.push((with_separator(&args[idx], ","), Vec::new())),
+                }
+            }
+            res.push(group);
+        }
+    }
+    res
+}
@@ -0,0 +1,21 @@
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, with_separator};
+
+/// Remove individual names from grouped imports like `use a::{b, c};`
+pub fn remove_use_list_items(_input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let mut res = Vec::new();
+    for list in descendants(root)
+        .into_iter()
+        .filter(|n| n.kind() == "use_list")
+    {
+        let mut cursor = list.walk();
+        for item in list.named_children(&mut cursor) {
+            if item.kind().ends_with("_comment") {
+                continue;
+            }
+            res.push(vec![(with_separator(&item, ","), Vec::new())]);
+        }
+    }
+    res
+}
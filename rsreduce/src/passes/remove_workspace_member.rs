@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tree_sitter_reduce::{Job, JobStatus, Pass, Test, TestResult};
+
+/// Returns the directory of the crate whose root is `root`, and the manifest of the
+/// workspace it is a member of, or `None` if it is not in a workspace
+///
+/// All the paths are relative to `workdir`. Crate roots are `src/lib.rs`, or
+/// `src/main.rs` for crates without a library, so that each crate has only one. The
+/// workspace is the closest parent directory whose `Cargo.toml` has a `[workspace]`
+/// section.
+fn workspace_of(workdir: &Path, root: &Path) -> anyhow::Result<Option<(PathBuf, PathBuf)>> {
+    let Some(src) = root.parent().filter(|p| p.ends_with("src")) else {
+        return Ok(None);
+    };
+    let Some(member) = src.parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return Ok(None);
+    };
+    let is_root = match root.file_name() {
+        Some(n) if n == "lib.rs" => true,
+        Some(n) if n == "main.rs" => !workdir.join(src).join("lib.rs").exists(),
+        _ => false,
+    };
+    if !is_root || !workdir.join(member).join("Cargo.toml").exists() {
+        return Ok(None);
+    }
+    for dir in member.ancestors().skip(1) {
+        let workspace = dir.join("Cargo.toml");
+        let path = workdir.join(&workspace);
+        if !path.exists() {
+            continue;
+        }
+        let contents =
+            std::fs::read_to_string(&path).with_context(|| format!("reading manifest {path:?}"))?;
+        if contents.lines().any(|l| l.trim() == "[workspace]") {
+            return Ok(Some((member.to_path_buf(), workspace)));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns `manifest` without the entries of `workspace.members` and
+/// `workspace.default-members` that are the path `member`
+///
+/// Members matched by a glob are left alone, as removing their directory is enough.
+fn remove_member_entries(manifest: &str, member: &str) -> anyhow::Result<String> {
+    let mut manifest = manifest
+        .parse::<toml_edit::Document>()
+        .context("parsing workspace manifest")?;
+    let Some(workspace) = manifest
+        .get_mut("workspace")
+        .and_then(|w| w.as_table_like_mut())
+    else {
+        return Ok(manifest.to_string());
+    };
+    for key in ["members", "default-members"] {
+        if let Some(members) = workspace.get_mut(key).and_then(|m| m.as_array_mut()) {
+            let first_prefix = members.get(0).and_then(|m| m.decor().prefix().cloned());
+            members.retain(|m| {
+                m.as_str().map_or(true, |m| {
+                    m.trim_start_matches("./").trim_end_matches('/') != member
+                })
+            });
+            // The new first element would keep the spacing after the removed comma
+            if let (Some(first), Some(prefix)) = (members.get_mut(0), first_prefix) {
+                first.decor_mut().set_prefix(prefix);
+            }
+        }
+    }
+    Ok(manifest.to_string())
+}
+
+/// Remove a whole crate of a workspace, along with its workspace membership
+///
+/// This runs on the root file of the workspace members, as the manifests are not
+/// reduced. Removing a crate that is irrelevant to the reproducer in one step is
+/// much faster than reducing each of its files, and is rejected by the test if other
+/// crates still depend on it.
+#[derive(Debug, Hash)]
+pub struct RemoveWorkspaceMember;
+
+impl Pass for RemoveWorkspaceMember {
+    fn reduce(
+        &self,
+        workdir: &Path,
+        test: &dyn Test,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        let Some((member, workspace)) = workspace_of(workdir, &job.path)? else {
+            return Ok(JobStatus::PassFailed(format!(
+                "{:?} is not the root of a workspace member",
+                job.path
+            )));
+        };
+        let relative = member
+            .strip_prefix(workspace.parent().unwrap())
+            .unwrap()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let workspace = workdir.join(workspace);
+        let contents = std::fs::read_to_string(&workspace)
+            .with_context(|| format!("reading manifest {workspace:?}"))?;
+        let new_contents = remove_member_entries(&contents, &relative)
+            .with_context(|| format!("removing {relative:?} from manifest {workspace:?}"))?;
+        if new_contents != contents {
+            std::fs::write(&workspace, new_contents)
+                .with_context(|| format!("writing manifest {workspace:?}"))?;
+        }
+        let dir = workdir.join(&member);
+        std::fs::remove_dir_all(&dir).with_context(|| format!("removing directory {dir:?}"))?;
+
+        let attempt = format!("Remove workspace member {member:?}");
+        match test
+            .test_interesting(workdir, kill_trigger, &attempt, job.id(0))
+            .context("running the test")?
+        {
+            TestResult::Interesting => Ok(JobStatus::Reduced(attempt)),
+            TestResult::NotInteresting => Ok(JobStatus::DidNotReduce),
+            TestResult::Interrupted => Ok(JobStatus::Interrupted),
+        }
+    }
+
+    fn applies_to(&self, path: &Path) -> bool {
+        path.file_name()
+            .map_or(false, |n| n == "lib.rs" || n == "main.rs")
+    }
+
+    fn extra_paths(&self, workdir: &Path, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let Some((member, workspace)) = workspace_of(workdir, path)? else {
+            return Ok(Vec::new());
+        };
+        let mut res = vec![workspace];
+        let dir = workdir.join(member);
+        // Build artifacts need not be restored if the crate turns out to be needed
+        for file in walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != "target")
+        {
+            let file = file.with_context(|| format!("walking directory {dir:?}"))?;
+            if file.file_type().is_dir() {
+                continue;
+            }
+            let file = file.path().strip_prefix(workdir).unwrap();
+            if file != path {
+                res.push(file.to_path_buf());
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::remove_member_entries;
+
+    #[test]
+    fn removes_members_and_default_members() {
+        let manifest = r#"[workspace]
+members = ["foo", "./bar/", "baz"]
+default-members = [
+    "bar",
+    "foo",
+]
+"#;
+        let res = remove_member_entries(manifest, "bar").unwrap();
+        let res = res.parse::<toml_edit::Document>().unwrap();
+        let strings = |key: &str| {
+            res["workspace"][key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m.as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strings("members"), ["foo", "baz"]);
+        assert_eq!(strings("default-members"), ["foo"]);
+    }
+
+    #[test]
+    fn keeps_other_arrays() {
+        let manifest = r#"[package]
+name = "root"
+exclude = ["foo"]
+
+[features]
+default = ["foo"]
+
+[workspace]
+members = ["foo"]
+exclude = ["foo"]
+"#;
+        let expected = manifest.replace("members = [\"foo\"]", "members = []");
+        assert_eq!(remove_member_entries(manifest, "foo").unwrap(), expected);
+    }
+
+    #[test]
+    fn handles_multiline_strings() {
+        let manifest = r#"[package]
+description = """
+members = ["foo"]
+"""
+
+[workspace]
+members = ['foo', 'bar']
+"#;
+        let expected = manifest.replace("['foo', 'bar']", "['bar']");
+        assert_eq!(remove_member_entries(manifest, "foo").unwrap(), expected);
+    }
+
+    #[test]
+    fn ignores_manifests_without_workspace() {
+        let manifest = "[package]\nname = \"foo\"\n\n[features]\ndefault = [\"foo\"]\n";
+        assert_eq!(remove_member_entries(manifest, "foo").unwrap(), manifest);
+    }
+}
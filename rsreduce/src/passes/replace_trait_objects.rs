@@ -0,0 +1,172 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::{descendants, trait_name_of};
+
+/// Returns the name of the trait of `ty`, if it is a `Box<dyn Trait>`
+///
+/// Additional bounds, like in `Box<dyn Trait + Send>`, are ignored.
+fn boxed_trait<'a>(input: &'a [u8], ty: &tree_sitter::Node) -> Option<&'a [u8]> {
+    if ty.kind() != "generic_type" || &input[ty.child_by_field_name("type")?.byte_range()] != b"Box"
+    {
+        return None;
+    }
+    let args = ty.child_by_field_name("type_arguments")?;
+    if args.named_child_count() != 1 {
+        return None;
+    }
+    let mut arg = args.named_child(0)?;
+    if arg.kind() == "bounded_type" {
+        arg = arg.named_child(0)?;
+    }
+    if arg.kind() != "dynamic_type" {
+        return None;
+    }
+    let name = trait_name_of(&arg.child_by_field_name("trait")?)?;
+    Some(&input[name.byte_range()])
+}
+
+/// Returns the first name in `node`, which is the constructed type for `Foo(..)`,
+/// `Foo { .. }`, `Foo::new(..)` or `Foo`
+fn leading_name<'a>(input: &'a [u8], node: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let leaf = descendants(node)
+        .into_iter()
+        .find(|n| n.is_named() && n.child_count() == 0)?;
+    let is_name = matches!(leaf.kind(), "identifier" | "type_identifier");
+    is_name.then(|| &input[leaf.byte_range()])
+}
+
+/// A `Box::new(value)` call, either parsed or inside a macro
+struct BoxNew<'a> {
+    /// The whole `Box::new(value)`
+    range: Range<usize>,
+    /// The `value`
+    value: Range<usize>,
+    /// The type of `value`, if it could be guessed
+    ty: Option<&'a [u8]>,
+}
+
+/// Returns all the `Box::new(value)` calls in `nodes`, including in macro arguments
+fn box_news<'a>(input: &'a [u8], nodes: &[tree_sitter::Node]) -> Vec<BoxNew<'a>> {
+    let mut res = Vec::new();
+    for node in nodes {
+        match node.kind() {
+            "call_expression" => {
+                let (Some(function), Some(args)) = (
+                    node.child_by_field_name("function"),
+                    node.child_by_field_name("arguments"),
+                ) else {
+                    continue;
+                };
+                if &input[function.byte_range()] != b"Box::new" || args.named_child_count() != 1 {
+                    continue;
+                }
+                let value = args.named_child(0).unwrap();
+                res.push(BoxNew {
+                    range: node.byte_range(),
+                    value: value.byte_range(),
+                    ty: leading_name(input, &value),
+                });
+            }
+            "token_tree" => {
+                // Look for `Box` `::` `new` `(...)`
+                let mut cursor = node.walk();
+                let children = node.children(&mut cursor).collect::<Vec<_>>();
+                for w in children.windows(4) {
+                    let is_box_new = &input[w[0].byte_range()] == b"Box"
+                        && w[1].kind() == "::"
+                        && &input[w[2].byte_range()] == b"new"
+                        && w[3].kind() == "token_tree"
+                        && input[w[3].start_byte()] == b'('
+                        && w[3].child_count() > 2;
+                    if !is_box_new {
+                        continue;
+                    }
+                    let (Some(open), Some(close)) =
+                        (w[3].child(0), w[3].child(w[3].child_count() - 1))
+                    else {
+                        continue;
+                    };
+                    res.push(BoxNew {
+                        range: w[0].start_byte()..w[3].end_byte(),
+                        value: open.end_byte()..close.start_byte(),
+                        ty: leading_name(input, &w[3]),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// Replace `Box<dyn Trait>` with the only concrete type that gets boxed into it
+///
+/// For each trait, the types implementing it are found from the impls in the file.
+/// If all the `Box::new` call sites constructing one of these types construct the
+/// same type, then all the `Box<dyn Trait>` are replaced with this type, the
+/// `Box::new` calls are removed, and so are the `as Box<dyn Trait>` casts. This is
+/// mostly useful for collections like `Vec<Box<dyn Trait>>`, where dynamic dispatch
+/// is often not actually needed.
+pub fn replace_trait_objects(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let mut implementors = BTreeMap::<&[u8], BTreeSet<&[u8]>>::new();
+    for item in nodes.iter().filter(|n| n.kind() == "impl_item") {
+        let (Some(trait_name), Some(ty)) = (
+            item.child_by_field_name("trait")
+                .and_then(|t| trait_name_of(&t)),
+            item.child_by_field_name("type")
+                .filter(|t| t.kind() == "type_identifier"),
+        ) else {
+            continue;
+        };
+        implementors
+            .entry(&input[trait_name.byte_range()])
+            .or_default()
+            .insert(&input[ty.byte_range()]);
+    }
+    let box_news = box_news(input, &nodes);
+    let mut res = Vec::new();
+    for (trait_name, types) in implementors {
+        let built = box_news
+            .iter()
+            .filter(|b| b.ty.map_or(false, |ty| types.contains(ty)))
+            .collect::<Vec<_>>();
+        let Some(concrete) = built.first().and_then(|b| b.ty) else {
+            continue;
+        };
+        if built.iter().any(|b| b.ty != Some(concrete)) {
+            continue;
+        }
+        let mut group = Vec::new();
+        for ty in nodes
+            .iter()
+            .filter(|n| boxed_trait(input, n) == Some(trait_name))
+        {
+            match ty.parent() {
+                Some(cast)
+                    if cast.kind() == "type_cast_expression"
+                        && cast.child_by_field_name("type") == Some(*ty) =>
+                {
+                    let Some(value) = cast.child_by_field_name("value") else {
+                        continue;
+                    };
+                    group.push((value.end_byte()..cast.end_byte(), Vec::new()));
+                }
+                _ => group.push((ty.byte_range(), concrete.to_vec())),
+            }
+        }
+        if group.is_empty() {
+            continue;
+        }
+        for b in built {
+            group.push((b.range.clone(), input[b.value.clone()].to_vec()));
+        }
+        res.push(group);
+    }
+    res
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use tree_sitter_reduce::passes::generic::EditGroup;
+
+use crate::util::descendants;
+
+/// Keywords that could otherwise be generated as short names
+const SHORT_KEYWORDS: &[&str] = &["as", "do", "fn", "if", "in"];
+
+/// Rename local variables and function parameters to short unique names
+///
+/// Only bindings with unambiguous scoping are renamed: simple identifier patterns of
+/// `let` declarations, whose scope is the rest of the enclosing block, and of
+/// function parameters, whose scope is the function body. Bindings used in ways
+/// that a rename could break, like shorthand field initializers or paths, are left
+/// alone.
+pub fn shorten_identifiers(input: &[u8], root: &tree_sitter::Node) -> Vec<EditGroup> {
+    let nodes = descendants(root);
+    let taken = nodes
+        .iter()
+        .filter(|n| n.child_count() == 0)
+        .map(|n| &input[n.byte_range()])
+        .collect::<HashSet<_>>();
+    let mut names = (1..)
+        .flat_map(short_names)
+        .filter(|n| !SHORT_KEYWORDS.contains(&n.as_str()) && !taken.contains(n.as_bytes()))
+        .peekable();
+
+    let mut res = Vec::new();
+    for node in &nodes {
+        let Some((binding, scope)) = binding_and_scope(node) else {
+            continue;
+        };
+        let name = &input[binding.byte_range()];
+        if names.peek().map_or(true, |n| n.len() >= name.len()) {
+            continue;
+        }
+        let uses = descendants(&scope)
+            .into_iter()
+            .filter(|n| n.kind() == "identifier" && &input[n.byte_range()] == name)
+            .filter(|n| n.start_byte() >= node.end_byte())
+            .collect::<Vec<_>>();
+        if uses.iter().any(|u| !is_renameable_use(u)) {
+            continue;
+        }
+        let new_name = names.next().expect("Just peeked a name");
+        res.push(
+            std::iter::once(binding)
+                .chain(uses)
+                .map(|n| (n.byte_range(), new_name.clone().into_bytes()))
+                .collect(),
+        );
+    }
+    res
+}
+
+/// If `node` is a binding this pass can rename, return it along with its scope
+fn binding_and_scope<'tree>(
+    node: &tree_sitter::Node<'tree>,
+) -> Option<(tree_sitter::Node<'tree>, tree_sitter::Node<'tree>)> {
+    let scope = match node.kind() {
+        "let_declaration" => node.parent().filter(|p| p.kind() == "block")?,
+        "parameter" => {
+            let function = node.parent()?.parent()?;
+            if function.kind() != "function_item" {
+                return None;
+            }
+            function.child_by_field_name("body")?
+        }
+        _ => return None,
+    };
+    let pattern = node.child_by_field_name("pattern")?;
+    (pattern.kind() == "identifier").then_some((pattern, scope))
+}
+
+fn is_renameable_use(node: &tree_sitter::Node) -> bool {
+    !matches!(
+        node.parent().map(|p| p.kind()),
+        Some("shorthand_field_initializer" | "field_pattern" | "scoped_identifier")
+    )
+}
+
+/// All the lowercase ascii names of length `len`
+fn short_names(len: u32) -> impl Iterator<Item = String> {
+    (0..26_u64.pow(len)).map(move |mut i| {
+        let mut name = String::new();
+        for _ in 0..len {
+            name.insert(0, char::from(b'a' + (i % 26) as u8));
+            i /= 26;
+        }
+        name
+    })
+}
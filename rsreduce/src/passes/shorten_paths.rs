@@ -0,0 +1,30 @@
+/// Returns whether `node` is a path with at least two segments
+fn is_scoped(node: &tree_sitter::Node) -> bool {
+    matches!(node.kind(), "scoped_identifier" | "scoped_type_identifier")
+}
+
+/// Drop the first segment of paths, like turning `a::b::Foo` into `b::Foo`
+///
+/// Turbofishes and generic arguments in the middle of the path are kept. Paths
+/// starting with `::` are left alone.
+pub fn shorten_paths(input: &[u8], node: &tree_sitter::Node) -> Option<Vec<u8>> {
+    if !is_scoped(node) {
+        return None;
+    }
+    let mut first = node.child_by_field_name("path")?;
+    loop {
+        let inner = match first.kind() {
+            "scoped_identifier" | "scoped_type_identifier" => first.child_by_field_name("path")?,
+            "generic_type" => first.child_by_field_name("type")?,
+            "generic_function" => first.child_by_field_name("function")?,
+            _ => break,
+        };
+        if !is_scoped(&first) && !is_scoped(&inner) {
+            // The first segment is generic, like `Foo::<T>`
+            break;
+        }
+        first = inner;
+    }
+    let separator = first.next_sibling().filter(|s| s.kind() == "::")?;
+    Some(input[separator.end_byte()..node.end_byte()].to_vec())
+}
@@ -0,0 +1,138 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use tree_sitter_reduce::{FnTest, Opt, Pass, ReductionEvent, TestResult};
+
+/// File reduced by the self-test, which is interesting as long as it has `fn keep`
+const FIXTURE: &str = r#"use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    fn new(x: i32, y: i32) -> Self {
+        Point { x, y }
+    }
+
+    fn norm(&self) -> i32 {
+        self.x * self.x + self.y * self.y
+    }
+}
+
+fn index(values: &[i32]) -> HashMap<i32, usize> {
+    let mut res = HashMap::new();
+    for (i, v) in values.iter().enumerate() {
+        res.insert(*v, i);
+    }
+    res
+}
+
+pub fn keep() -> i32 {
+    let point = Point::new(1, 2);
+    let index = index(&[1, 2, 3]);
+    point.norm() + index.len() as i32
+}
+"#;
+
+const FIXTURE_NAME: &str = "lib.rs";
+
+/// Check that reducing twice with the same seed gives the same result
+///
+/// This reduces a built-in file twice, with a single job, and fails if the two
+/// reductions did not find the exact same reductions in the same order.
+#[derive(Debug, structopt::StructOpt)]
+pub struct SelftestOpt {
+    /// Seed to use for both reductions
+    #[structopt(long, default_value = "0")]
+    random_seed: u64,
+}
+
+/// Reduce the fixture with `seed`, returning the result and the reductions found
+fn reduce_fixture(
+    seed: u64,
+    passes: &[Arc<dyn Pass>],
+    polish_passes: &[Arc<dyn Pass>],
+) -> anyhow::Result<(String, Vec<(String, u64, u64)>)> {
+    let dir = tempfile::tempdir().context("creating temporary directory")?;
+    let snap_dir = tempfile::tempdir().context("creating temporary directory")?;
+    let file = dir.path().join(FIXTURE_NAME);
+    std::fs::write(&file, FIXTURE).with_context(|| format!("writing fixture to {file:?}"))?;
+
+    let mut opt = Opt::new(file.clone(), snap_dir.path().to_path_buf());
+    opt.jobs = 1;
+    opt.random_seed = Some(seed);
+    opt.converge_after = Some(100);
+    let reductions = Arc::new(Mutex::new(Vec::new()));
+    // Keep the sender alive, as the runner considers a disconnection to be a bug
+    let (_killer, kill_trigger) = crossbeam_channel::bounded(1);
+    tree_sitter_reduce::reduce(
+        opt,
+        |_| Ok(vec![PathBuf::from(FIXTURE_NAME)]),
+        FnTest::new(|root: &Path, _: &crossbeam_channel::Receiver<()>| {
+            let contents = std::fs::read_to_string(root.join(FIXTURE_NAME))?;
+            Ok(match contents.contains("fn keep") {
+                true => TestResult::Interesting,
+                false => TestResult::NotInteresting,
+            })
+        }),
+        passes,
+        polish_passes,
+        kill_trigger,
+        {
+            let reductions = reductions.clone();
+            move |e: &ReductionEvent| {
+                if let ReductionEvent::Reduced {
+                    pass,
+                    old_size,
+                    new_size,
+                    ..
+                } = e
+                {
+                    let reduction = (pass.clone(), *old_size, *new_size);
+                    reductions.lock().unwrap().push(reduction);
+                }
+            }
+        },
+    )
+    .context("reducing the fixture")?;
+
+    let reduced =
+        std::fs::read_to_string(&file).with_context(|| format!("reading reduced {file:?}"))?;
+    let reductions = reductions.lock().unwrap().clone();
+    Ok((reduced, reductions))
+}
+
+pub fn run(
+    opt: SelftestOpt,
+    passes: &[Arc<dyn Pass>],
+    polish_passes: &[Arc<dyn Pass>],
+) -> anyhow::Result<()> {
+    let seed = opt.random_seed;
+    println!("Reducing the fixture twice with seed {seed}…");
+    let (first, first_reductions) = reduce_fixture(seed, passes, polish_passes)?;
+    let (second, second_reductions) = reduce_fixture(seed, passes, polish_passes)?;
+    if let Some(i) = (0..first_reductions.len().max(second_reductions.len()))
+        .find(|&i| first_reductions.get(i) != second_reductions.get(i))
+    {
+        anyhow::bail!(
+            "The reductions diverged at reduction {i}: {:?} the first time, but {:?} the second time",
+            first_reductions.get(i),
+            second_reductions.get(i),
+        );
+    }
+    anyhow::ensure!(
+        first == second,
+        "The reductions ended with different results:\n---\n{first}---\nand:\n---\n{second}---",
+    );
+    println!(
+        "Both reductions found the same {} reductions, and ended with:\n---\n{first}---",
+        first_reductions.len(),
+    );
+    Ok(())
+}
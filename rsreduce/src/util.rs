@@ -0,0 +1,75 @@
+use std::ops::Range;
+
+/// Returns `true` if `node` or any of its descendants matches `pred`
+pub fn any_descendant(
+    node: &tree_sitter::Node,
+    pred: &impl Fn(&tree_sitter::Node) -> bool,
+) -> bool {
+    if pred(node) {
+        return true;
+    }
+    let mut cursor = node.walk();
+    let res = node
+        .children(&mut cursor)
+        .any(|child| any_descendant(&child, pred));
+    res
+}
+
+/// Returns `node` and all its descendants, in pre-order
+pub fn descendants<'tree>(node: &tree_sitter::Node<'tree>) -> Vec<tree_sitter::Node<'tree>> {
+    let mut res = vec![*node];
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        res.extend(descendants(&child));
+    }
+    res
+}
+
+/// Returns the name of the function called by `generic_function` node `call`
+pub fn called_name<'a>(input: &'a [u8], call: &tree_sitter::Node) -> Option<&'a [u8]> {
+    let function = call.child_by_field_name("function")?;
+    let name = match function.kind() {
+        "identifier" => function,
+        "scoped_identifier" => function.child_by_field_name("name")?,
+        "field_expression" => function.child_by_field_name("field")?,
+        _ => return None,
+    };
+    Some(&input[name.byte_range()])
+}
+
+/// Returns the identifier naming the trait in the `trait` field of an impl
+pub fn trait_name_of<'tree>(node: &tree_sitter::Node<'tree>) -> Option<tree_sitter::Node<'tree>> {
+    match node.kind() {
+        "type_identifier" => Some(*node),
+        "scoped_type_identifier" => node.child_by_field_name("name"),
+        "generic_type" => trait_name_of(&node.child_by_field_name("type")?),
+        _ => None,
+    }
+}
+
+/// Returns the name of the parameter declared by `param`, a child of `type_parameters`
+pub fn type_param_name<'a>(input: &'a [u8], param: &tree_sitter::Node) -> Option<&'a [u8]> {
+    match param.kind() {
+        "type_identifier" => Some(&input[param.byte_range()]),
+        "constrained_type_parameter" | "optional_type_parameter" => param
+            .child_by_field_name("left")
+            .or_else(|| param.child_by_field_name("name"))
+            .map(|n| &input[n.byte_range()]),
+        _ => None,
+    }
+}
+
+/// Returns the byte range of `node`, extended to cover one adjacent `separator`
+///
+/// The separator following `node` is preferred, and the one preceding it is used
+/// if there is none. This makes it possible to remove an element from a list while
+/// keeping the list syntactically valid.
+pub fn with_separator(node: &tree_sitter::Node, separator: &str) -> Range<usize> {
+    if let Some(next) = node.next_sibling().filter(|n| n.kind() == separator) {
+        return node.start_byte()..next.end_byte();
+    }
+    if let Some(prev) = node.prev_sibling().filter(|n| n.kind() == separator) {
+        return prev.start_byte()..node.end_byte();
+    }
+    node.byte_range()
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+
+use crate::TestResult;
+
+/// Prefix of the file in which the test cache is saved in the snapshot directory
+///
+/// It is followed by the test's identity, so that results of a different test are
+/// never reused.
+pub(crate) const TEST_CACHE_PREFIX: &str = ".test-cache-";
+
+/// Remove the test caches in `snap_dir` other than `keep`
+///
+/// They were saved by runs with a different test, so their results cannot be reused.
+pub(crate) fn remove_stale_test_caches(snap_dir: &Path, keep: &Path) -> anyhow::Result<()> {
+    let entries = std::fs::read_dir(snap_dir)
+        .with_context(|| format!("listing snapshot directory {snap_dir:?}"))?;
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("listing snapshot directory {snap_dir:?}"))?
+            .path();
+        let is_test_cache = path.file_name().map_or(false, |n| {
+            n.to_string_lossy().starts_with(TEST_CACHE_PREFIX)
+        });
+        if is_test_cache && path != keep {
+            tracing::info!("Removing test cache {path:?}, that was saved with a different test");
+            std::fs::remove_file(&path)
+                .with_context(|| format!("removing stale test cache {path:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Results of the test for the states that were already tested
+///
+/// States are identified by a hash of the contents of the files being reduced.
+/// When the cache is full, the oldest results are forgotten first.
+#[derive(Debug)]
+pub(crate) struct TestCache {
+    /// Files being reduced, whose contents identify a state
+    files: Vec<PathBuf>,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// Whether the state with this hash was interesting
+    results: HashMap<u64, bool>,
+    /// Hashes of the states, from the least recently inserted
+    order: VecDeque<u64>,
+}
+
+impl TestCache {
+    pub(crate) fn new(mut files: Vec<PathBuf>, max_entries: usize) -> Self {
+        files.sort();
+        TestCache {
+            files,
+            max_entries,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Hash the state of the files being reduced in `root`
+    pub(crate) fn hash_state(&self, root: &Path) -> anyhow::Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        for f in &self.files {
+            let path = root.join(f);
+            f.hash(&mut hasher);
+            match std::fs::read(&path) {
+                Ok(contents) => Some(contents).hash(&mut hasher),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    None::<Vec<u8>>.hash(&mut hasher)
+                }
+                Err(e) => return Err(e).with_context(|| format!("reading {path:?} to hash it")),
+            }
+        }
+        Ok(hasher.finish())
+    }
+
+    pub(crate) fn get(&self, state: u64) -> Option<TestResult> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .results
+            .get(&state)
+            .map(|&interesting| match interesting {
+                true => TestResult::Interesting,
+                false => TestResult::NotInteresting,
+            })
+    }
+
+    /// Record the result of the test on `state`, ignoring interrupted tests
+    pub(crate) fn insert(&self, state: u64, res: TestResult) {
+        let interesting = match res {
+            TestResult::Interesting => true,
+            TestResult::NotInteresting => false,
+            TestResult::Interrupted => return,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.results.insert(state, interesting).is_none() {
+            entries.order.push_back(state);
+        }
+        while entries.order.len() > self.max_entries {
+            let oldest = entries.order.pop_front().unwrap();
+            entries.results.remove(&oldest);
+        }
+    }
+
+    /// Load the results saved by `save` in `path`, if it exists
+    pub(crate) fn load(&self, path: &Path) -> anyhow::Result<()> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("reading test cache {path:?}")),
+        };
+        for line in contents.lines() {
+            let (state, res) = line
+                .split_once(' ')
+                .with_context(|| format!("parsing line {line:?} of test cache {path:?}"))?;
+            let state = u64::from_str_radix(state, 16)
+                .with_context(|| format!("parsing line {line:?} of test cache {path:?}"))?;
+            let res = match res {
+                "interesting" => TestResult::Interesting,
+                "not-interesting" => TestResult::NotInteresting,
+                _ => anyhow::bail!("parsing line {line:?} of test cache {path:?}: unknown result"),
+            };
+            self.insert(state, res);
+        }
+        tracing::info!(
+            "Loaded {} cached test results from {path:?}",
+            self.entries.lock().unwrap().order.len()
+        );
+        Ok(())
+    }
+
+    /// Save the results to `path`, in a way that is atomic for `load`
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut file = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("creating temporary file for test cache in {dir:?}"))?;
+        {
+            let entries = self.entries.lock().unwrap();
+            for state in &entries.order {
+                let res = match entries.results[state] {
+                    true => "interesting",
+                    false => "not-interesting",
+                };
+                writeln!(file, "{state:016x} {res}")
+                    .with_context(|| format!("writing test cache {path:?}"))?;
+            }
+        }
+        file.persist(path)
+            .with_context(|| format!("saving test cache to {path:?}"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+/// Something that happened during the reduction, see `reduce`
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ReductionEvent {
+    /// A pass managed to reduce a file
+    Reduced {
+        /// Human-readable name of the pass
+        pass: String,
+        /// Path of the reduced file, relative to the root path
+        path: PathBuf,
+        /// Size of the file before the reduction, in bytes
+        old_size: u64,
+        /// Size of the file after the reduction, in bytes
+        new_size: u64,
+    },
+
+    /// A snapshot was written
+    Snapshot {
+        /// Path of the snapshot directory
+        path: PathBuf,
+        /// Total size of the reduced files, in bytes
+        total_size: u64,
+    },
+
+    /// The reduction converged, and only the polish passes are left to run
+    Converged {
+        /// Total size of the reduced files, in bytes
+        total_size: u64,
+    },
+}
+
+/// Callback to call on each `ReductionEvent`
+pub(crate) type EventCallback = Box<dyn Fn(&ReductionEvent) + Send>;
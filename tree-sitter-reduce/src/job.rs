@@ -1,8 +1,9 @@
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use crate::Pass;
@@ -40,9 +41,38 @@ impl JobStatus {
 #[derive(Clone, Debug)]
 pub struct Job {
     pub path: PathBuf,
+
+    /// Other files this job edits along with `path`, for jobs spanning multiple files
+    ///
+    /// This is set from `Pass::extra_paths` for the passes that edit multiple files,
+    /// and for the replays of successful edits on similar files.
+    pub extra_paths: Vec<PathBuf>,
+
     pub(crate) pass: Arc<dyn Pass>,
     pub random_seed: u64,
     pub recent_success_rate: u8,
+
+    /// Same as `recent_success_rate`, but for the recent jobs of this pass on all files
+    pub pass_success_rate: u8,
+
+    /// Maximum wall-clock time this job should spend trying out attempts
+    ///
+    /// Multi-attempt passes should stop trying new attempts once this is exceeded,
+    /// so that the runner gets a chance to re-evaluate which file and pass to try.
+    pub time_budget: Option<Duration>,
+
+    /// Maximum number of attempts this job should try out
+    ///
+    /// Multi-attempt passes should only try out this many of their attempts, keeping
+    /// the most reducing ones, for the same reason as `time_budget`.
+    pub max_attempts: Option<usize>,
+
+    /// Whether dichotomy passes should skip the attempts that are small compared to
+    /// the file
+    ///
+    /// This makes the jobs on large files only try out coarse edits, and finer and
+    /// finer ones as the file shrinks.
+    pub size_aware_dichotomy: bool,
 }
 
 pub(crate) struct JobResult {
@@ -59,22 +89,47 @@ impl Job {
     ) -> anyhow::Result<Job> {
         Ok(Job {
             path,
+            extra_paths: Vec::new(),
             pass,
             random_seed,
             recent_success_rate,
+            pass_success_rate: u8::MAX / 2,
+            time_budget: None,
+            max_attempts: None,
+            size_aware_dichotomy: false,
         })
     }
 
+    /// All the files this job can edit, starting with `path`
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.path.as_path()).chain(self.extra_paths.iter().map(PathBuf::as_path))
+    }
+
     /// Identifier of this job, supposed to be passed to `Test::test_interesting`
     ///
     /// The `attempt_number` is the number of the attempt for multi-attempt passes.
     pub fn id(&self, attempt_number: usize) -> u64 {
+        self.hasher(attempt_number).finish()
+    }
+
+    /// Identifier of this job, that also identifies the concrete edit being attempted
+    ///
+    /// This should be preferred over `id` when the pass knows what it is editing,
+    /// for instance the replaced byte ranges and their replacements. This way, the
+    /// identifier shown to the user differs for any two distinct edits.
+    pub fn attempt_id(&self, attempt_number: usize, attempt: &impl Hash) -> u64 {
+        let mut hasher = self.hasher(attempt_number);
+        attempt.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hasher(&self, attempt_number: usize) -> DefaultHasher {
         let mut hasher = DefaultHasher::new();
         self.path.hash(&mut hasher);
         self.pass.dyn_hash(&mut hasher);
         self.random_seed.hash(&mut hasher);
         self.recent_success_rate.hash(&mut hasher);
         attempt_number.hash(&mut hasher);
-        hasher.finish()
+        hasher
     }
 }
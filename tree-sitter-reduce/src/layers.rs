@@ -0,0 +1,124 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tempfile::TempDir;
+
+use crate::util::{make_tempdir, remove_file_and_empty_parents};
+
+/// Name of the manifest of a layer, in the snapshot directory of the layer
+///
+/// With `--changed-only`, most snapshots are layers: they only contain the files
+/// that changed since their base snapshot, along with this manifest naming the base
+/// and listing the files removed since then. The full tree is rebuilt by applying
+/// the layers in order on top of the first snapshot of the chain, a full copy.
+const LAYER_MANIFEST: &str = ".tree-sitter-reduce-layer";
+
+/// Write in `dir` the layer holding the `changed` files of `workdir`, on top of the
+/// snapshot named `base`
+///
+/// The files of `changed` that no longer exist in `workdir` are recorded as removed.
+pub(crate) fn write_layer(
+    workdir: &Path,
+    dir: &Path,
+    base: &str,
+    changed: &HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut manifest = format!("base {base}\n");
+    let mut changed = changed.iter().collect::<Vec<_>>();
+    changed.sort();
+    for path in changed {
+        let (from, to) = (workdir.join(path), dir.join(path));
+        if !from.exists() {
+            manifest.push_str(&format!("removed {}\n", path.display()));
+            continue;
+        }
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("recursively creating directory {parent:?}"))?;
+        }
+        std::fs::copy(&from, &to)
+            .with_context(|| format!("copying changed file {from:?} to layer {to:?}"))?;
+    }
+    let path = dir.join(LAYER_MANIFEST);
+    std::fs::write(&path, manifest).with_context(|| format!("writing layer manifest {path:?}"))
+}
+
+/// Returns the name of the base of `snapshot`, and the files removed since then
+///
+/// Returns `None` if `snapshot` is a full snapshot rather than a layer.
+fn read_manifest(snapshot: &Path) -> anyhow::Result<Option<(String, Vec<PathBuf>)>> {
+    let path = snapshot.join(LAYER_MANIFEST);
+    let manifest = match std::fs::read_to_string(&path) {
+        Ok(manifest) => manifest,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading layer manifest {path:?}")),
+    };
+    let mut lines = manifest.lines();
+    let base = lines
+        .next()
+        .and_then(|l| l.strip_prefix("base "))
+        .with_context(|| format!("layer manifest {path:?} does not start with its base"))?;
+    let removed = lines
+        .map(|l| {
+            l.strip_prefix("removed ")
+                .map(PathBuf::from)
+                .with_context(|| format!("unexpected line {l:?} in layer manifest {path:?}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Some((String::from(base), removed)))
+}
+
+/// Returns the name of the snapshot `snapshot` is a layer on top of, if any
+pub(crate) fn layer_base(snapshot: &Path) -> anyhow::Result<Option<String>> {
+    Ok(read_manifest(snapshot)?.map(|(base, _)| base))
+}
+
+/// Returns the path to a full copy of `snapshot`
+///
+/// This is `snapshot` itself if it is a full snapshot. If it is a layer, the full
+/// tree is rebuilt in a temporary directory, in `tmp_dir` if set, that is returned
+/// too so that it lives as long as needed.
+pub(crate) fn resolve_snapshot(
+    snapshot: &Path,
+    tmp_dir: Option<&Path>,
+) -> anyhow::Result<(PathBuf, Option<TempDir>)> {
+    let mut chain = vec![snapshot.to_path_buf()];
+    while let Some(base) = layer_base(chain.last().unwrap())? {
+        let base = snapshot.with_file_name(base);
+        anyhow::ensure!(
+            base.exists(),
+            "snapshot {base:?}, that later snapshots are based on, is missing"
+        );
+        chain.push(base);
+    }
+    if chain.len() == 1 {
+        return Ok((snapshot.to_path_buf(), None));
+    }
+    tracing::info!(
+        "Rebuilding layered snapshot {snapshot:?} from {} layers",
+        chain.len()
+    );
+    let dir = make_tempdir("tree-sitter-reduce-snapshot-", None, tmp_dir)?;
+    for layer in chain.iter().rev() {
+        fs_extra::dir::copy(
+            layer,
+            dir.path(),
+            &fs_extra::dir::CopyOptions::new()
+                .content_only(true)
+                .overwrite(true),
+        )
+        .with_context(|| format!("copying layer {layer:?} to {:?}", dir.path()))?;
+        if let Some((_, removed)) = read_manifest(layer)? {
+            for path in removed {
+                remove_file_and_empty_parents(dir.path(), &path)?;
+            }
+        }
+    }
+    let manifest = dir.path().join(LAYER_MANIFEST);
+    std::fs::remove_file(&manifest)
+        .with_context(|| format!("removing layer manifest {manifest:?}"))?;
+    Ok((dir.path().to_path_buf(), Some(dir)))
+}
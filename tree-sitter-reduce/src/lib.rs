@@ -1,13 +1,21 @@
+mod cache;
+mod event;
 mod job;
+mod layers;
 mod pass;
+mod replay;
+mod report;
 mod run;
 mod runner;
 mod test;
 mod util;
 mod workers;
 
+pub use event::ReductionEvent;
 pub use job::{Job, JobStatus};
 pub use pass::Pass;
 pub mod passes;
-pub use run::{run, Opt};
-pub use test::{ShellTest, Test, TestResult};
+pub use run::{reduce, run, Opt, ProgressStyle, Shard};
+#[cfg(unix)]
+pub use test::SocketTest;
+pub use test::{FnTest, ShellTest, StdinTest, Test, TestResult};
@@ -1,4 +1,9 @@
-use std::{collections::hash_map::DefaultHasher, fmt::Debug, hash::Hash, path::Path};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::Hash,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     job::{Job, JobStatus},
@@ -12,13 +17,14 @@ pub trait Pass: Debug + DynHash + Send + Sync {
     ///
     /// Note that for proper operation this MUST BE DETERMINISTIC! For this reason, a
     /// `random_seed` argument is provided, which the pass can use to initialize an RNG.
-    /// Also, this path should not edit the other files in `workdir`, but only use it
-    /// to run the test on them.
+    /// Also, this path should not edit the other files in `workdir`, except the ones
+    /// in `job.extra_paths`, but only use it to run the test on them.
     ///
     /// The `recent_success_rate` parameter is passed so that the passes can define how
     /// aggressive they want to be. Basically, the number will get closer to `u8::MAX`
     /// if recent passes have led to successful reductions, and closer to `0` if recent
-    /// passes have failed to reduce the file size.
+    /// passes have failed to reduce the file size. Similarly, `job.pass_success_rate`
+    /// tracks how often this pass recently managed to reduce any file.
     ///
     /// This function is expected to return `true` if it successfully reduced the input,
     /// and `false` if the current input cannot be handled by this pass. Returning errors
@@ -38,6 +44,54 @@ pub trait Pass: Debug + DynHash + Send + Sync {
         job: &Job,
         kill_trigger: &crossbeam_channel::Receiver<()>,
     ) -> anyhow::Result<JobStatus>;
+
+    /// List the extensions (without the leading `.`) of the files this pass applies to
+    ///
+    /// If empty (the default), the pass applies to all the files.
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Whether this pass should ever be run on the file at `path`
+    ///
+    /// By default, this checks `path` against `extensions`. Passes that need more
+    /// specific routing, for instance based on the file name, can override it.
+    fn applies_to(&self, path: &Path) -> bool {
+        let extensions = self.extensions();
+        extensions.is_empty()
+            || path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map_or(false, |e| extensions.contains(&e))
+    }
+
+    /// List the files other than `path` that running this pass on `path` may edit
+    ///
+    /// The returned paths are relative to `workdir`, that is in the same state as the
+    /// one the job will run in, and end up in `job.extra_paths`. They can also be
+    /// removed by the pass, in which case the runner stops reducing them. By default,
+    /// the pass only edits `path`.
+    fn extra_paths(&self, _workdir: &Path, _path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(Vec::new())
+    }
+
+    /// Language this pass parses the files it applies to as, if it uses tree-sitter
+    fn language(&self) -> Option<tree_sitter::Language> {
+        None
+    }
+
+    /// List the sizes, in bytes, of the edits this pass would attempt for `job`
+    ///
+    /// This must not edit the files nor run the test. It is only used to display
+    /// statistics, so returning `None` (the default) is always correct.
+    fn attempt_sizes(
+        &self,
+        _workdir: &Path,
+        _job: &Job,
+        _kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<Option<Vec<usize>>> {
+        Ok(None)
+    }
 }
 
 pub trait DynHash {
@@ -20,7 +20,8 @@ pub trait DichotomyPass {
     /// succeed but most reducing) and ending with the smallest (most likely to
     /// succeed but least interesting).
     ///
-    /// `DichotomyPass` will then run these attempts in-order.
+    /// `DichotomyPass` will then run these attempts in-order, only keeping the first
+    /// `job.max_attempts` ones if it is set.
     ///
     /// One example such result is, for a pass that would remove lines `x..y`,
     /// and assuming the file has for instance 16 lines:
@@ -39,6 +40,10 @@ pub trait DichotomyPass {
 
     /// Actually attempt the reduction suggested by `attempt`
     ///
+    /// `attempt_number` is the index of `attempt` in the list returned by
+    /// `list_attempts`, and `total_attempts` the length of said list. They should be
+    /// displayed to the user, to show how far along the dichotomy the pass is.
+    ///
     /// Note that the file currently at `workdir/job.path` could have been changed
     /// by previous attempts of this same pass. The pass should read the original
     /// file contents from the `parsed` argument, carried over from `list_attempts`.
@@ -48,10 +53,28 @@ pub trait DichotomyPass {
         test: &dyn Test,
         attempt: Self::Attempt,
         attempt_number: usize,
+        total_attempts: usize,
         job: &Job,
         parsed: &Self::Parsed,
         kill_trigger: &crossbeam_channel::Receiver<()>,
     ) -> anyhow::Result<JobStatus>;
+
+    /// See `Pass::extensions`
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// See `Pass::language`
+    fn language(&self) -> Option<tree_sitter::Language> {
+        None
+    }
+
+    /// Number of bytes of the original file that `attempt` would edit
+    ///
+    /// Returns `None` (the default) if unknown. This is only used for statistics.
+    fn attempt_size(&self, _parsed: &Self::Parsed, _attempt: &Self::Attempt) -> Option<usize> {
+        None
+    }
 }
 
 impl<T> Pass for T
@@ -65,7 +88,8 @@ where
         job: &Job,
         kill_trigger: &crossbeam_channel::Receiver<()>,
     ) -> anyhow::Result<JobStatus> {
-        let (parsed, attempts) = match self.list_attempts(workdir, job, kill_trigger)? {
+        let start = std::time::Instant::now();
+        let (parsed, mut attempts) = match self.list_attempts(workdir, job, kill_trigger)? {
             None => {
                 return Ok(JobStatus::PassFailed(format!(
                     "Dichotomy pass {self:?} failed to find replacements in {:?}",
@@ -74,17 +98,31 @@ where
             }
             Some(r) => r,
         };
+        if let Some(max_attempts) = job.max_attempts {
+            attempts.truncate(max_attempts);
+        }
         if attempts.is_empty() {
             return Ok(JobStatus::PassFailed(String::from(format!(
                 "No option to choose from for {self:?}",
             ))));
         }
+        let total_attempts = attempts.len();
         for (attempt_number, attempt) in attempts.into_iter().enumerate() {
+            if let Some(budget) = job.time_budget {
+                if start.elapsed() >= budget {
+                    tracing::debug!(
+                        "Pass {self:?} on {:?} ran out of time budget after {attempt_number} attempts",
+                        job.path,
+                    );
+                    break;
+                }
+            }
             match self.attempt_reduce(
                 workdir,
                 test,
                 attempt,
                 attempt_number,
+                total_attempts,
                 job,
                 &parsed,
                 kill_trigger,
@@ -95,4 +133,27 @@ where
         }
         Ok(JobStatus::DidNotReduce)
     }
+
+    fn extensions(&self) -> &[&str] {
+        DichotomyPass::extensions(self)
+    }
+
+    fn language(&self) -> Option<tree_sitter::Language> {
+        DichotomyPass::language(self)
+    }
+
+    fn attempt_sizes(
+        &self,
+        workdir: &Path,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<Option<Vec<usize>>> {
+        let Some((parsed, attempts)) = self.list_attempts(workdir, job, kill_trigger)? else {
+            return Ok(Some(Vec::new()));
+        };
+        Ok(attempts
+            .iter()
+            .map(|a| self.attempt_size(&parsed, a))
+            .collect())
+    }
 }
@@ -4,9 +4,19 @@ use crate::{JobStatus, Pass, TestResult};
 
 /// Trim end-of-line whitespace and discard empty lines
 #[derive(Debug, Hash)]
-pub struct DiscardWhitespace;
+pub struct DiscardWhitespace {
+    /// Extensions of the files to discard whitespace from
+    ///
+    /// The pass will only be run on files with one of these extensions. If empty,
+    /// it will be run on all files.
+    pub extensions: &'static [&'static str],
+}
 
 impl Pass for DiscardWhitespace {
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
     fn reduce(
         &self,
         workdir: &std::path::Path,
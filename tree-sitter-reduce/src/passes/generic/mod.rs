@@ -1,7 +1,11 @@
 mod discard_whitespace;
 mod remove_lines;
+mod tree_sitter_group_replace;
+mod tree_sitter_query_replace;
 mod tree_sitter_replace;
 
 pub use discard_whitespace::DiscardWhitespace;
 pub use remove_lines::RemoveLines;
+pub use tree_sitter_group_replace::{EditGroup, TreeSitterGroupReplace};
+pub use tree_sitter_query_replace::TreeSitterQueryReplace;
 pub use tree_sitter_replace::TreeSitterReplace;
@@ -48,12 +48,24 @@ impl DichotomyPass for RemoveLines {
         Ok(Some((file_contents, res)))
     }
 
+    fn attempt_size(&self, file_contents: &Self::Parsed, attempt: &Self::Attempt) -> Option<usize> {
+        Some(
+            file_contents
+                .lines()
+                .enumerate()
+                .filter(|(l, _)| attempt.contains(l))
+                .map(|(_, line)| line.len() + 1)
+                .sum(),
+        )
+    }
+
     fn attempt_reduce(
         &self,
         workdir: &Path,
         test: &dyn Test,
         attempt: Self::Attempt,
         attempt_number: usize,
+        total_attempts: usize,
         job: &Job,
         file_contents: &Self::Parsed,
         kill_trigger: &crossbeam_channel::Receiver<()>,
@@ -71,9 +83,14 @@ impl DichotomyPass for RemoveLines {
         std::fs::write(&path, new_data)
             .with_context(|| format!("writing file {path:?} with reduced data"))?;
 
+        let attempt_id = job.attempt_id(attempt_number, &attempt);
         let attempt = format!("Remove lines {attempt:?} of file {:?}", job.path);
+        let attempt_name = format!(
+            "{attempt} (attempt {}/{total_attempts})",
+            attempt_number + 1
+        );
         match test
-            .test_interesting(workdir, kill_trigger, &attempt, job.id(attempt_number))
+            .test_interesting(workdir, kill_trigger, &attempt_name, attempt_id)
             .context("running the test")?
         {
             TestResult::Interesting => Ok(JobStatus::Reduced(attempt)),
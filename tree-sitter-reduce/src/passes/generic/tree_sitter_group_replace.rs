@@ -0,0 +1,226 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, VecDeque},
+    fmt::Debug,
+    hash::Hash,
+    ops::Range,
+};
+
+use anyhow::Context;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{passes::DichotomyPass, JobStatus, TestResult};
+
+/// A set of edits that only make sense when all applied together
+///
+/// Each edit replaces the bytes in the range by the `Vec<u8>`.
+pub type EditGroup = Vec<(Range<usize>, Vec<u8>)>;
+
+pub struct TreeSitterGroupReplace<F>
+where
+    F: Fn(&[u8], &tree_sitter::Node) -> Vec<EditGroup>,
+{
+    /// Language to parse the input as
+    pub language: tree_sitter::Language,
+
+    /// Extensions of the files written in `language`
+    ///
+    /// The pass will only be run on files with one of these extensions. If empty,
+    /// it will be run on all files.
+    pub extensions: &'static [&'static str],
+
+    /// Human-readable name of this pass
+    pub name: String,
+
+    /// Edit group finder
+    ///
+    /// This is a function that takes as parameter the full file as bytes and
+    /// the root tree-sitter `Node` of the file, and returns the list of edit
+    /// groups this pass should try applying.
+    ///
+    /// Each edit group is either applied as a whole, or not at all. This makes
+    /// it possible to implement coordinated edits, like removing a declaration
+    /// along with all its uses, or removing a list element along with its
+    /// separator.
+    ///
+    /// Groups are allowed to overlap. In this case, only one of the overlapping
+    /// groups is applied by each attempt, and the others are left out as a whole.
+    /// Edits overlapping within a single group are fine too: the edit that starts
+    /// first (or the outermost one, for edits that start at the same byte) wins,
+    /// and the other ones are ignored.
+    pub group_finder: F,
+}
+
+impl<F> Debug for TreeSitterGroupReplace<F>
+where
+    F: Fn(&[u8], &tree_sitter::Node) -> Vec<EditGroup>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tree-Sitter: {}", self.name)
+    }
+}
+
+impl<F> Hash for TreeSitterGroupReplace<F>
+where
+    F: Fn(&[u8], &tree_sitter::Node) -> Vec<EditGroup>,
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.language.hash(state);
+        self.name.hash(state);
+        // self.group_finder.hash(state);
+    }
+}
+
+impl<F> DichotomyPass for TreeSitterGroupReplace<F>
+where
+    F: Fn(&[u8], &tree_sitter::Node) -> Vec<EditGroup>,
+{
+    // Groups of byte ranges to replace and the value to replace with
+    type Attempt = Vec<EditGroup>;
+
+    type Parsed = Vec<u8>;
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn language(&self) -> Option<tree_sitter::Language> {
+        Some(self.language)
+    }
+
+    fn attempt_size(&self, _parsed: &Self::Parsed, attempt: &Self::Attempt) -> Option<usize> {
+        Some(attempt.iter().flatten().map(|(range, _)| range.len()).sum())
+    }
+
+    fn list_attempts(
+        &self,
+        workdir: &std::path::Path,
+        job: &crate::Job,
+        _kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<Option<(Self::Parsed, VecDeque<Self::Attempt>)>> {
+        // Load the file
+        let path = workdir.join(&job.path);
+        let file_contents =
+            std::fs::read(&path).with_context(|| format!("reading file {path:?}"))?;
+
+        // Parse the file
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(self.language)
+            .expect("Failed to make a parser with configured language");
+        let tree = match parser.parse(&file_contents, None) {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        // Collect all the edit groups
+        let mut groups = (self.group_finder)(&file_contents, &tree.root_node());
+        groups.retain(|g| !g.is_empty());
+        if groups.is_empty() {
+            return Ok(None);
+        }
+
+        // Select the groups to try applying, starting with all of them and then
+        // trying smaller and smaller random windows
+        let mut rng = StdRng::seed_from_u64(job.random_seed);
+        groups.shuffle(&mut rng);
+        let mut attempts = VecDeque::new();
+        let mut len = groups.len();
+        while len > 0 {
+            let start_at = rng.gen_range(0..(groups.len() - len + 1));
+            attempts.push_back(groups[start_at..(start_at + len)].to_vec());
+            len /= 2;
+        }
+
+        Ok(Some((file_contents, attempts)))
+    }
+
+    fn attempt_reduce(
+        &self,
+        workdir: &std::path::Path,
+        test: &dyn crate::Test,
+        attempt: Self::Attempt,
+        attempt_number: usize,
+        total_attempts: usize,
+        job: &crate::Job,
+        file_contents: &Self::Parsed,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<crate::JobStatus> {
+        let path = workdir.join(&job.path);
+
+        let attempt = non_overlapping_edits(attempt);
+
+        let mut new_data = Vec::with_capacity(file_contents.len());
+        let mut file_cursor = 0;
+        let mut removed_size = 0;
+        let mut replacement_size = 0;
+        for (range, replace_with) in attempt.iter() {
+            new_data.extend_from_slice(&file_contents[file_cursor..range.start]);
+            new_data.extend_from_slice(replace_with);
+            removed_size += range.len();
+            replacement_size += replace_with.len();
+            file_cursor = range.end;
+        }
+        new_data.extend_from_slice(&file_contents[file_cursor..]);
+
+        std::fs::write(&path, new_data)
+            .with_context(|| format!("writing file {path:?} with reduced data"))?;
+
+        let attempt_id = job.attempt_id(attempt_number, &attempt);
+        let attempt = format!(
+            "{}: Replacing {removed_size}B with {replacement_size}B (ranges {attempt:?})",
+            self.name,
+        );
+
+        let attempt_name = format!(
+            "{attempt} (attempt {}/{total_attempts})",
+            attempt_number + 1
+        );
+        match test
+            .test_interesting(workdir, kill_trigger, &attempt_name, attempt_id)
+            .context("running the test")?
+        {
+            TestResult::Interesting => Ok(JobStatus::Reduced(attempt)),
+            TestResult::NotInteresting => Ok(JobStatus::DidNotReduce),
+            TestResult::Interrupted => Ok(JobStatus::Interrupted),
+        }
+    }
+}
+
+/// Returns the edits of `groups`, sorted, leaving out the groups that overlap a
+/// previous one
+///
+/// Groups are always applied as a whole or not at all, as applying only some of
+/// their edits would most likely make the file invalid.
+fn non_overlapping_edits(groups: Vec<EditGroup>) -> EditGroup {
+    // Disjoint ranges of the edits kept so far, sorted by start then end
+    let mut kept = BTreeSet::new();
+    let mut res = Vec::new();
+    for mut group in groups {
+        // Drop the edits that overlap with a previous one in the same group
+        group.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+        let mut file_cursor = 0;
+        group.retain(|(range, _)| {
+            let keep = range.start >= file_cursor;
+            if keep {
+                file_cursor = range.end;
+            }
+            keep
+        });
+        // As the kept ranges are disjoint, checking the last one starting before
+        // the end of the edit is enough
+        let overlaps = group.iter().any(|(range, _)| {
+            kept.range(..(range.end, 0))
+                .next_back()
+                .map_or(false, |&(_, end)| end > range.start)
+        });
+        if overlaps {
+            continue;
+        }
+        kept.extend(group.iter().map(|(range, _)| (range.start, range.end)));
+        res.extend(group);
+    }
+    // Insertions come before the removals starting at the same byte
+    res.sort_by_key(|(range, _)| (range.start, range.end));
+    res
+}
@@ -0,0 +1,185 @@
+use std::{collections::VecDeque, fmt::Debug, hash::Hash};
+
+use anyhow::Context;
+
+use super::{EditGroup, TreeSitterGroupReplace};
+use crate::{passes::DichotomyPass, JobStatus};
+
+type GroupFinder = Box<dyn Send + Sync + Fn(&[u8], &tree_sitter::Node) -> Vec<EditGroup>>;
+
+/// Replace the nodes matched by a tree-sitter query
+///
+/// In each match of the query, the nodes captured as `@replace` are replaced with the
+/// replacement template. In the template, `{name}` is substituted with the text of
+/// the node captured as `@name` in the same match, and `{{` and `}}` are literal
+/// braces. All the replacements of a single match are applied together. Nodes whose
+/// text already appears in their replacement are left alone, as replacing them could
+/// not reduce the input.
+///
+/// This makes it possible to define passes without writing a node matcher, for
+/// instance from a configuration file.
+pub struct TreeSitterQueryReplace {
+    inner: TreeSitterGroupReplace<GroupFinder>,
+}
+
+enum Segment {
+    Text(Vec<u8>),
+    Capture(u32),
+}
+
+impl TreeSitterQueryReplace {
+    /// Build the pass, validating `query` and `replacement`
+    pub fn new(
+        language: tree_sitter::Language,
+        extensions: &'static [&'static str],
+        name: String,
+        query: &str,
+        replacement: &str,
+    ) -> anyhow::Result<Self> {
+        let query = tree_sitter::Query::new(language, query)
+            .with_context(|| format!("parsing query for pass {name:?}"))?;
+        let replace_idx = query
+            .capture_index_for_name("replace")
+            .with_context(|| format!("query for pass {name:?} has no `@replace` capture"))?;
+        let template = parse_template(&query, replacement)
+            .with_context(|| format!("parsing replacement template for pass {name:?}"))?;
+        let group_finder: GroupFinder = Box::new(move |input, root| {
+            let mut cursor = tree_sitter::QueryCursor::new();
+            cursor
+                .matches(&query, *root, input)
+                .map(|m| {
+                    let replacement = template
+                        .iter()
+                        .flat_map(|s| match s {
+                            Segment::Text(t) => &t[..],
+                            Segment::Capture(idx) => m
+                                .captures
+                                .iter()
+                                .find(|c| c.index == *idx)
+                                .map_or(&[][..], |c| &input[c.node.byte_range()]),
+                        })
+                        .copied()
+                        .collect::<Vec<u8>>();
+                    // Like `TreeSitterReplace`, ignore nodes that could not be reduced,
+                    // to avoid endlessly "reducing" to the same input. This includes
+                    // the empty nodes, like the `MISSING` ones of broken input
+                    m.captures
+                        .iter()
+                        .filter(|c| c.index == replace_idx)
+                        .filter(|c| {
+                            let bytes = &input[c.node.byte_range()];
+                            !bytes.is_empty()
+                                && !replacement.windows(bytes.len()).any(|b| b == bytes)
+                        })
+                        .map(|c| (c.node.byte_range(), replacement.clone()))
+                        .collect()
+                })
+                .collect()
+        });
+        Ok(TreeSitterQueryReplace {
+            inner: TreeSitterGroupReplace {
+                language,
+                extensions,
+                name,
+                group_finder,
+            },
+        })
+    }
+}
+
+fn parse_template(query: &tree_sitter::Query, template: &str) -> anyhow::Result<Vec<Segment>> {
+    let mut res = Vec::new();
+    let mut text = Vec::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                text.push(b'{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                text.push(b'}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .with_context(|| format!("unclosed `{{` in replacement {template:?}"))?;
+                let capture = &rest[..end];
+                let idx = query.capture_index_for_name(capture).with_context(|| {
+                    format!("replacement {template:?} uses unknown capture {capture:?}")
+                })?;
+                res.push(Segment::Text(std::mem::take(&mut text)));
+                res.push(Segment::Capture(idx));
+                chars = rest[(end + 1)..].chars();
+            }
+            '}' => anyhow::bail!("unmatched `}}` in replacement {template:?}"),
+            c => text.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    res.push(Segment::Text(text));
+    Ok(res)
+}
+
+impl Debug for TreeSitterQueryReplace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl Hash for TreeSitterQueryReplace {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
+impl DichotomyPass for TreeSitterQueryReplace {
+    type Attempt = Vec<EditGroup>;
+
+    type Parsed = Vec<u8>;
+
+    fn extensions(&self) -> &[&str] {
+        DichotomyPass::extensions(&self.inner)
+    }
+
+    fn language(&self) -> Option<tree_sitter::Language> {
+        DichotomyPass::language(&self.inner)
+    }
+
+    fn attempt_size(&self, parsed: &Self::Parsed, attempt: &Self::Attempt) -> Option<usize> {
+        self.inner.attempt_size(parsed, attempt)
+    }
+
+    fn list_attempts(
+        &self,
+        workdir: &std::path::Path,
+        job: &crate::Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<Option<(Self::Parsed, VecDeque<Self::Attempt>)>> {
+        self.inner.list_attempts(workdir, job, kill_trigger)
+    }
+
+    fn attempt_reduce(
+        &self,
+        workdir: &std::path::Path,
+        test: &dyn crate::Test,
+        attempt: Self::Attempt,
+        attempt_number: usize,
+        total_attempts: usize,
+        job: &crate::Job,
+        parsed: &Self::Parsed,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        self.inner.attempt_reduce(
+            workdir,
+            test,
+            attempt,
+            attempt_number,
+            total_attempts,
+            job,
+            parsed,
+            kill_trigger,
+        )
+    }
+}
@@ -6,6 +6,10 @@ use tree_sitter::TreeCursor;
 
 use crate::{passes::DichotomyPass, JobStatus, TestResult};
 
+/// With `Job::size_aware_dichotomy`, attempts never replace less than this fraction
+/// of the file, apart from the first one that replaces all the matched nodes
+const SIZE_AWARE_MIN_FRACTION: usize = 64;
+
 pub struct TreeSitterReplace<F>
 where
     F: Fn(&[u8], &tree_sitter::Node) -> Option<Vec<u8>>,
@@ -13,6 +17,12 @@ where
     /// Language to parse the input as
     pub language: tree_sitter::Language,
 
+    /// Extensions of the files written in `language`
+    ///
+    /// The pass will only be run on files with one of these extensions. If empty,
+    /// it will be run on all files.
+    pub extensions: &'static [&'static str],
+
     /// Human-readable name of this pass
     pub name: String,
 
@@ -224,6 +234,18 @@ where
 
     type Parsed = Vec<u8>;
 
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn language(&self) -> Option<tree_sitter::Language> {
+        Some(self.language)
+    }
+
+    fn attempt_size(&self, _parsed: &Self::Parsed, attempt: &Self::Attempt) -> Option<usize> {
+        Some(attempt.iter().map(|(range, _)| range.len()).sum())
+    }
+
     fn list_attempts(
         &self,
         workdir: &std::path::Path,
@@ -258,11 +280,15 @@ where
         if cur_bytes == 0 {
             return Ok(None);
         }
+        let min_aim = match job.size_aware_dichotomy {
+            true => file_contents.len() / SIZE_AWARE_MIN_FRACTION,
+            false => 0,
+        };
         attempts.push_back(interesting);
         'finished: loop {
             let mut attempt = attempts[attempts.len() - 1].clone();
             aim_at_bytes /= 2;
-            if aim_at_bytes == 0 {
+            if aim_at_bytes == 0 || aim_at_bytes < min_aim {
                 break;
             }
             let mut removed_this_round = 0;
@@ -310,6 +336,7 @@ where
         test: &dyn crate::Test,
         attempt: Self::Attempt,
         attempt_number: usize,
+        total_attempts: usize,
         job: &crate::Job,
         file_contents: &Self::Parsed,
         kill_trigger: &crossbeam_channel::Receiver<()>,
@@ -332,13 +359,18 @@ where
         std::fs::write(&path, new_data)
             .with_context(|| format!("writing file {path:?} with reduced data"))?;
 
+        let attempt_id = job.attempt_id(attempt_number, &attempt);
         let attempt = format!(
             "{}: Replacing {removed_size}B with {replacement_size}B (ranges {attempt:?})",
             self.name,
         );
 
+        let attempt_name = format!(
+            "{attempt} (attempt {}/{total_attempts})",
+            attempt_number + 1
+        );
         match test
-            .test_interesting(workdir, kill_trigger, &attempt, job.id(attempt_number))
+            .test_interesting(workdir, kill_trigger, &attempt_name, attempt_id)
             .context("running the test")?
         {
             TestResult::Interesting => Ok(JobStatus::Reduced(attempt)),
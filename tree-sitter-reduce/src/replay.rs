@@ -0,0 +1,164 @@
+use std::{
+    fmt::{self, Debug},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{Job, JobStatus, Pass, Test, TestResult};
+
+/// Number of times `needle` occurs in `haystack`, counting overlapping occurrences
+fn occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack
+        .windows(needle.len())
+        .filter(|w| *w == needle)
+        .count()
+}
+
+/// Returns the lines that changed between `old` and `new`, in each of them
+///
+/// The changed bytes are extended to whole lines, so that the edit can be found in
+/// other files without matching at random places. Returns `None` if nothing changed.
+fn changed_lines(old: &[u8], new: &[u8]) -> Option<(Range<usize>, Range<usize>)> {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(o, n)| o == n)
+        .count();
+    if prefix == old.len() && prefix == new.len() {
+        return None;
+    }
+    let start = old[..prefix]
+        .iter()
+        .rposition(|c| *c == b'\n')
+        .map_or(0, |p| p + 1);
+    let suffix_start = old.len() - suffix;
+    let old_end = old[suffix_start..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map_or(old.len(), |p| suffix_start + p);
+    let new_end = new.len() - (old.len() - old_end);
+    Some((start..old_end, start..new_end))
+}
+
+/// Pass replacing `old` with `new` in all the files of the job that contain it once
+///
+/// This replays an edit that was found to be successful on one file on similar files,
+/// like near-duplicate generated files, testing all the edited files at once.
+#[derive(Hash)]
+pub(crate) struct ReplayEdit {
+    /// Name of the pass that found the edit
+    pass: String,
+    old: Vec<u8>,
+    new: Vec<u8>,
+}
+
+impl Debug for ReplayEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Replay of {} on similar files", self.pass)
+    }
+}
+
+impl ReplayEdit {
+    /// Returns the job replaying the successful `job` on the other files of `files`
+    ///
+    /// `before` and `after` are the working directories before and after `job`. The
+    /// files similar to `job.path` are the ones with the same extension that contain
+    /// the lines it changed exactly once. Returns `None` if there are no such files.
+    pub(crate) fn job_for(
+        job: &Job,
+        before: &Path,
+        after: &Path,
+        files: &[PathBuf],
+        random_seed: u64,
+    ) -> anyhow::Result<Option<Job>> {
+        let (old_path, new_path) = (before.join(&job.path), after.join(&job.path));
+        let old = std::fs::read(&old_path)
+            .with_context(|| format!("reading file {old_path:?} before the reduction"))?;
+        let new = std::fs::read(&new_path)
+            .with_context(|| format!("reading reduced file {new_path:?}"))?;
+        let Some((old_range, new_range)) = changed_lines(&old, &new) else {
+            return Ok(None);
+        };
+        let (old, new) = (&old[old_range], &new[new_range]);
+        if old.is_empty() {
+            return Ok(None);
+        }
+        let mut similar = Vec::new();
+        for path in files {
+            if *path == job.path || path.extension() != job.path.extension() {
+                continue;
+            }
+            let real_path = before.join(path);
+            let contents =
+                std::fs::read(&real_path).with_context(|| format!("reading file {real_path:?}"))?;
+            if occurrences(&contents, old) == 1 {
+                similar.push(path.clone());
+            }
+        }
+        if similar.is_empty() {
+            return Ok(None);
+        }
+        let path = similar.remove(0);
+        Ok(Some(Job {
+            path,
+            extra_paths: similar,
+            pass: std::sync::Arc::new(ReplayEdit {
+                pass: format!("{:?}", job.pass),
+                old: old.to_vec(),
+                new: new.to_vec(),
+            }),
+            random_seed,
+            ..job.clone()
+        }))
+    }
+}
+
+impl Pass for ReplayEdit {
+    fn reduce(
+        &self,
+        workdir: &Path,
+        test: &dyn Test,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        let mut edited = 0;
+        for path in job.paths() {
+            let path = workdir.join(path);
+            let contents =
+                std::fs::read(&path).with_context(|| format!("reading file {path:?}"))?;
+            if occurrences(&contents, &self.old) != 1 {
+                continue;
+            }
+            let start = contents
+                .windows(self.old.len())
+                .position(|w| w == self.old)
+                .unwrap();
+            let replaced = [
+                &contents[..start],
+                &self.new,
+                &contents[start + self.old.len()..],
+            ]
+            .concat();
+            std::fs::write(&path, replaced)
+                .with_context(|| format!("writing replayed edit to {path:?}"))?;
+            edited += 1;
+        }
+        if edited == 0 {
+            return Ok(JobStatus::PassFailed(String::from(
+                "No similar file still contains the edited lines",
+            )));
+        }
+        let attempt_name = format!("{self:?} ({edited} files)");
+        match test.test_interesting(workdir, kill_trigger, &attempt_name, job.id(0))? {
+            TestResult::Interesting => Ok(JobStatus::Reduced(attempt_name)),
+            TestResult::NotInteresting => Ok(JobStatus::DidNotReduce),
+            TestResult::Interrupted => Ok(JobStatus::Interrupted),
+        }
+    }
+}
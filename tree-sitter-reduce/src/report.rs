@@ -0,0 +1,90 @@
+use std::{fmt::Write, path::PathBuf};
+
+use anyhow::Context;
+
+/// Quote `arg` so that it can be pasted in a shell
+fn shell_quote(arg: &str) -> String {
+    let is_safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    match is_safe {
+        true => String::from(arg),
+        false => format!("'{}'", arg.replace('\'', r"'\''")),
+    }
+}
+
+/// Markdown summary of the reduction, written when the reducer exits
+///
+/// This is meant to be pasted as-is in a bug report. The parts known before
+/// starting the reduction are set here, and the statistics are passed to `write`.
+pub(crate) struct Report {
+    /// File in which to write the report
+    pub(crate) path: PathBuf,
+
+    /// Seed of the random number generation
+    pub(crate) seed: u64,
+
+    /// Description of the test, as returned by `Test::description`
+    pub(crate) test: Option<String>,
+}
+
+/// Statistics about a finished reduction
+pub(crate) struct ReductionStats {
+    /// Total size of the files to reduce before the reduction, in bytes
+    pub(crate) initial_size: u64,
+
+    /// Total size of the files to reduce after the reduction, in bytes
+    pub(crate) final_size: u64,
+
+    /// Number of times the test was run
+    pub(crate) tests_run: u64,
+
+    /// Number of bytes removed by each pass, for the passes that removed any
+    pub(crate) removed_by_pass: Vec<(String, u64)>,
+}
+
+impl Report {
+    pub(crate) fn write(&self, stats: ReductionStats) -> anyhow::Result<()> {
+        let ReductionStats {
+            initial_size,
+            final_size,
+            tests_run,
+            mut removed_by_pass,
+        } = stats;
+        let reduction = 100. * (1. - final_size as f64 / initial_size.max(1) as f64);
+        let command = std::env::args()
+            .map(|a| shell_quote(&a))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut res = String::from("# Reduction report\n\n");
+        writeln!(res, "- Original size: {initial_size} bytes").unwrap();
+        writeln!(
+            res,
+            "- Reduced size: {final_size} bytes ({reduction:.1}% smaller)"
+        )
+        .unwrap();
+        writeln!(res, "- Tests run: {tests_run}").unwrap();
+        writeln!(res, "- Random seed: {}", self.seed).unwrap();
+        if let Some(test) = &self.test {
+            writeln!(res, "- Test: `{test}`").unwrap();
+        }
+        writeln!(res, "- Reducer command: `{command}`").unwrap();
+        res.push_str("\n## Passes that reduced the input\n\n");
+        if removed_by_pass.is_empty() {
+            res.push_str("No pass managed to reduce the input.\n");
+        } else {
+            removed_by_pass.sort_by(|(p1, r1), (p2, r2)| r2.cmp(r1).then(p1.cmp(p2)));
+            res.push_str("| Pass | Bytes removed |\n|---|---:|\n");
+            for (pass, removed) in removed_by_pass {
+                writeln!(res, "| {} | {removed} |", pass.replace('|', "\\|")).unwrap();
+            }
+        }
+
+        std::fs::write(&self.path, res)
+            .with_context(|| format!("writing report to {:?}", self.path))?;
+        tracing::info!("Wrote the reduction report to {:?}", self.path);
+        Ok(())
+    }
+}
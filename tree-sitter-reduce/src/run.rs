@@ -1,6 +1,8 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
+    ffi::OsString,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::Arc,
     time::Duration,
 };
@@ -8,7 +10,16 @@ use std::{
 use anyhow::Context;
 use rand::{rngs::StdRng, SeedableRng};
 
-use crate::{runner::Runner, util::init_env, Pass, Test};
+use crate::{
+    event::EventCallback,
+    job::Job,
+    layers::resolve_snapshot,
+    report::Report,
+    runner::{Runner, RunnerConfig},
+    util::{clean_tempdir_root, init_env, is_snapshot, wrap_single_file, SINGLE_FILE_RECORD},
+    workers::WorkerConfig,
+    Pass, ReductionEvent, Test,
+};
 
 #[derive(Debug, structopt::StructOpt)]
 pub struct Opt {
@@ -17,15 +28,19 @@ pub struct Opt {
     /// The interestingness test will be run in a copy this folder. Note that copies
     /// will happen only during the startup of this program. So the folder can be
     /// changed after the program confirms it's running.
+    ///
+    /// This can also be a single file, in which case the test will be run in a
+    /// folder containing only a copy of it. The latest snapshot of the file is then
+    /// written back to it when the reducer exits.
     #[structopt(long, required_unless("resume"))]
-    root_path: Option<PathBuf>,
+    pub root_path: Option<PathBuf>,
 
     /// Resume from a previous reducer run
     ///
     /// This only works if there are already snapshots in the snapshot directory, ie.
     /// a previous reducer run was interrupted.
     #[structopt(long)]
-    resume: bool,
+    pub resume: bool,
 
     /// If this option is passed, then only the file passed to it will be reduced
     ///
@@ -34,9 +49,23 @@ pub struct Opt {
     /// that this program knows how to reduce, will be reduced.
     ///
     /// For instance, in a Cargo workspace, you can use `--file this-crate/src/*.rs`
-    /// to attempt reduction of only one crate.
+    /// to attempt reduction of only one crate. Passes that edit several files at once
+    /// only run if all these files are listed.
     #[structopt(long = "file")]
-    only_files: Option<Vec<PathBuf>>,
+    pub only_files: Option<Vec<PathBuf>>,
+
+    /// Only reduce the `i`-th of `n` disjoint subsets of the files, in `i/n` format
+    ///
+    /// This makes it possible to split the reduction of a huge tree across multiple
+    /// invocations, possibly on different machines, by running one invocation for
+    /// each shard from `1/n` to `n/n`. The files are split deterministically, so
+    /// the invocations never edit the same files. Each invocation must use its own
+    /// snapshot directory, and the reduced files of each shard then need to be put
+    /// back together by hand. Passes that edit several files at once, like the
+    /// removal of whole workspace members, only run if all these files are in the
+    /// shard.
+    #[structopt(long)]
+    pub shard: Option<Shard>,
 
     /// The path to which to save snapshots
     ///
@@ -46,7 +75,7 @@ pub struct Opt {
     /// Inside, the reducer will write folders that are reduced copies of the root
     /// folder, each folder name being the timestamp of the snapshot.
     #[structopt(long)]
-    snapshot_directory: PathBuf,
+    pub snapshot_directory: PathBuf,
 
     /// At which frequency (in seconds) to snapshot the state of reduction
     ///
@@ -61,7 +90,16 @@ pub struct Opt {
     // TODO: allow customization (and disabling) of cleanup command for rsreduce
     // TODO: add a max_snapshots parameter to limit the number of kept snapshots for very long runs
     #[structopt(long, default_value = "10")]
-    snapshot_interval: u64,
+    pub snapshot_interval: u64,
+
+    /// Only take a single snapshot, when the reducer exits
+    ///
+    /// This avoids regularly copying the files to the snapshot directory, which
+    /// is not needed when only the final result matters. The snapshot is also taken
+    /// when the reducer is interrupted, but all the reductions are lost if it gets
+    /// killed without a chance to exit cleanly.
+    #[structopt(long, conflicts_with("snapshot-interval"))]
+    pub no_snapshots: bool,
 
     /// Maximum number of snapshots to keep
     ///
@@ -71,7 +109,38 @@ pub struct Opt {
     /// directory, it could lead to snapshots never being recorded, or to these
     /// things being deleted.
     #[structopt(long, default_value = "10")]
-    max_snapshots: usize,
+    pub max_snapshots: usize,
+
+    /// Only write the files that changed since the previous snapshot
+    ///
+    /// Copying the whole root path for each snapshot is wasteful on large trees,
+    /// where each snapshot usually only changes a few files. With this option, most
+    /// snapshots only contain the files that changed since the previous one, along
+    /// with a `.tree-sitter-reduce-layer` file listing the previous snapshot and the
+    /// files that were removed since then. A full snapshot is still taken once every
+    /// `--max-snapshots` snapshots. Such snapshots cannot be used as-is, but
+    /// `--resume` rebuilds the full tree from them.
+    #[structopt(long, conflicts_with("format-command"))]
+    pub changed_only: bool,
+
+    /// Command to run on each reduced file when taking a snapshot, like `rustfmt`
+    ///
+    /// The command is called with the path to the file to format as its only
+    /// argument, and must edit it in-place. This makes snapshots more readable. If
+    /// the formatted snapshot is not interesting any longer, it is kept unformatted.
+    /// Note that this runs the test once more for each snapshot. Formatting only
+    /// affects the snapshots, reduction continues on the unformatted files.
+    #[structopt(long)]
+    pub format_command: Option<PathBuf>,
+
+    /// Command to run once before starting, to check that the environment is sane
+    ///
+    /// The command is run without arguments in the root path, before anything is
+    /// copied, and the reduction is aborted if it fails. It must not edit the root
+    /// path. This gives a quick failure for setup mistakes like a missing toolchain,
+    /// instead of waiting for the copy and the first run of the test.
+    #[structopt(long)]
+    pub verify_command: Option<PathBuf>,
 
     /// Number of interestingness tests to run in parallel
     ///
@@ -79,22 +148,342 @@ pub struct Opt {
     /// reduction go faster, as it will run only one test at once and thus lose less
     /// valid reductions due to two workers finding a reduction at once.
     #[structopt(long, short, default_value = "4")]
-    jobs: usize,
+    pub jobs: usize,
+
+    /// Minimum number of interestingness tests to keep running in parallel
+    ///
+    /// When workers die too often, which usually means that the test is unstable,
+    /// they stop being restarted, until only this many are left. If workers keep
+    /// dying past that point, the reducer exits with an error.
+    #[structopt(long, default_value = "1")]
+    pub min_jobs: usize,
+
+    /// Maximum time (in seconds) a single job can spend trying out attempts
+    ///
+    /// Some passes try out many attempts in a row, which can take a long time on
+    /// big files. When this is set, such passes stop after the first attempt that
+    /// finishes past this budget, so that the reducer can more frequently
+    /// re-evaluate which file and pass to try next. By default, there is no limit.
+    #[structopt(long)]
+    pub pass_budget: Option<u64>,
+
+    /// Maximum number of attempts a single job can try out
+    ///
+    /// Some passes try out many attempts in a row, starting from the most reducing
+    /// ones. When this is set, such passes only try out this many attempts, so that
+    /// the reducer can more frequently re-evaluate which file and pass to try next.
+    /// This helps on large trees with many files. By default, there is no limit.
+    #[structopt(long)]
+    pub max_attempts_per_job: Option<usize>,
+
+    /// Make the edits attempted on large files coarser
+    ///
+    /// Tree-sitter passes first try replacing all the nodes they match at once, and
+    /// then smaller and smaller subsets of them. When a pass matches many small
+    /// nodes of a large file, this means many attempts that each barely reduce the
+    /// file. With this option, the subsets never get smaller than 1/64th of the
+    /// file, so that large files first get reduced in big chunks, and finer and
+    /// finer ones as they shrink. Polish passes are not affected.
+    #[structopt(long)]
+    pub size_aware_dichotomy: bool,
+
+    /// Number of jobs in a row that must fail to reduce for the reduction to be considered converged
+    ///
+    /// Once the reduction has converged, the polish passes (if any) are run once on
+    /// each file, a final snapshot is taken, and the reducer exits. By default, the
+    /// reduction is never considered converged, and the reducer runs until it is
+    /// interrupted.
+    #[structopt(long)]
+    pub converge_after: Option<usize>,
+
+    /// Stop reducing once the files to reduce total less than this many bytes
+    ///
+    /// When this happens, a final snapshot is taken and the reducer exits, without
+    /// running the polish passes. This is useful when any reproducer small enough to
+    /// be attached to a bug report will do. By default, there is no target size.
+    #[structopt(long)]
+    pub target_size: Option<u64>,
+
+    /// Prefer reducing the largest files first
+    ///
+    /// By default, the file to reduce is chosen uniformly at random. With this
+    /// option, it is chosen with a probability that depends on its size at the
+    /// beginning of the reduction, and more and more on how often reductions
+    /// recently succeeded on it as the total size decreases. This makes large trees
+    /// shrink faster at the start.
+    #[structopt(long)]
+    pub prioritize_largest_files: bool,
+
+    /// Try each pass on each file in turn, in a fixed order, instead of randomly
+    ///
+    /// Together with `--random-seed` and a single job, this makes the reduction
+    /// fully reproducible, which is useful for testing the reducer itself or
+    /// comparing the effectiveness of passes.
+    #[structopt(long, conflicts_with("prioritize-largest-files"))]
+    pub round_robin: bool,
+
+    /// Replay successful edits on the similar files
+    ///
+    /// Some reproducers contain near-duplicate files, like generated variants of
+    /// the same code, that all need the same reductions. With this option, after
+    /// each successful reduction, the lines it changed are replaced in the same way
+    /// in all the other files with the same extension that contain them exactly
+    /// once. All these files are tested together, and only kept reduced if the
+    /// result is still interesting.
+    #[structopt(long)]
+    pub symmetric_files: bool,
+
+    /// Pause the reduction while this file exists
+    ///
+    /// While the file exists, no new jobs are started, and the workers idle once
+    /// their current job is done, for instance to free the CPU for something else.
+    /// Removing the file resumes the reduction where it was. The file is checked
+    /// every second.
+    #[structopt(long)]
+    pub pause_file: Option<PathBuf>,
+
+    /// Warn at the end about the files tree-sitter does not manage to fully parse
+    ///
+    /// Tree-sitter passes silently stop reducing the parts of files where parsing
+    /// fails, so this helps understand why some files did not reduce further.
+    #[structopt(long)]
+    pub report_parse_errors: bool,
+
+    /// File in which to write a summary of the reduction when exiting
+    ///
+    /// The summary is written in markdown, to be pasted in a bug report. It lists
+    /// the original and reduced sizes, the number of tests run, the passes that
+    /// reduced the input, the random seed, the test and the reducer's command line.
+    #[structopt(long)]
+    pub report_file: Option<PathBuf>,
 
     /// Seed for the random number generation
     #[structopt(long)]
-    random_seed: Option<u64>,
+    pub random_seed: Option<u64>,
+
+    /// File from which to read the seed for the random number generation
+    ///
+    /// If the file does not exist, a random seed is chosen and written to it. This
+    /// makes it possible to reproduce a run by passing the same file again. The
+    /// file only contains the seed, as a decimal integer.
+    #[structopt(long, conflicts_with("random-seed"))]
+    pub seed_file: Option<PathBuf>,
+
+    /// Re-run the test on each successful reduction before accepting it
+    ///
+    /// This halves the speed at which reductions are accepted, but avoids flaky
+    /// tests leading the reduction astray, by only accepting reductions that were
+    /// interesting twice in a row.
+    #[structopt(long)]
+    pub double_check: bool,
+
+    /// Run the test this many times on each attempt, and keep the majority result
+    ///
+    /// This makes the reduction robust to flaky tests, like timing-dependent
+    /// crashes: a single spurious result can neither reject a valid reduction nor
+    /// accept an invalid one. The test is run again only until the majority is
+    /// known, and ties count as not interesting, so odd numbers work best. By
+    /// default, the test is run once.
+    #[structopt(long, default_value = "1")]
+    pub vote: usize,
+
+    /// Number of times to retry the test when it fails to run, before giving up
+    ///
+    /// A test that fails to run, rather than reporting that the input is not
+    /// interesting, makes the reducer consider the worker's copy of the root path
+    /// broken, and rebuild it from scratch. Retrying after a short delay avoids this
+    /// on transient issues, like a locked file or a temporary lack of resources. By
+    /// default, the test is retried once.
+    #[structopt(long, default_value = "1")]
+    pub test_retries: usize,
+
+    /// Directory in which to put the copies of the root path used for reduction
+    ///
+    /// Each worker works on its own copy of the root path, and backs up the file it
+    /// is reducing before each job. Pointing this to a RAM-backed filesystem, like
+    /// `/dev/shm`, can make reduction noticeably faster on slow disks. By default,
+    /// the system's temporary directory is used.
+    #[structopt(long)]
+    pub tmp_dir: Option<PathBuf>,
+
+    /// Directory in which to put the copies of the root path, under stable names
+    ///
+    /// This is meant for debugging the reducer itself. Instead of randomly-named
+    /// directories, the runner uses `runner` and each worker uses `worker-<id>`
+    /// inside this directory, where `<id>` counts the workers spawned so far. This
+    /// makes the paths reproducible across runs. Directories with these names left
+    /// over by a previous run are removed when starting.
+    #[structopt(long, conflicts_with("tmp-dir"))]
+    pub tempdir_root: Option<PathBuf>,
+
+    /// Back up the files with git in each worker, instead of copying them
+    ///
+    /// Each worker tracks its copy of the root path in a git repository, in which
+    /// the file being reduced is staged before each job, restored from the index
+    /// if the job fails, and committed if it succeeds. This can be faster than
+    /// copying very large files, and gives a history of the reductions in each
+    /// worker. The repository is kept next to the copy, so the test never sees a
+    /// `.git` directory. If setting up the repository fails, for instance if git
+    /// is not installed, the files are copied as usual.
+    #[structopt(long)]
+    pub git_backup: bool,
+
+    /// Maximum number of test results to remember
+    ///
+    /// When this is set, the result of the test is remembered for each tested state
+    /// of the files being reduced, and reused instead of re-running the test when
+    /// the same state comes up again. The results are also saved in the snapshot
+    /// directory alongside the snapshots, and reused when resuming with the same
+    /// test. The oldest results are forgotten first. By default, no results are
+    /// remembered.
+    #[structopt(long, default_value = "0")]
+    pub test_cache_size: usize,
 
     /// Skip checking whether the provided target directory is interesting
     #[structopt(long)]
-    do_not_validate_input: bool,
+    pub do_not_validate_input: bool,
+
+    /// Abort if checking that the target directory is interesting takes longer than
+    /// this many seconds
+    ///
+    /// The reduction runs the test thousands of times, so a test that takes minutes
+    /// makes it impractical. This gives a quick failure for such tests, so that they
+    /// can be sped up before starting over, for instance by only building the crate
+    /// that reproduces the issue. By default, tests can be arbitrarily slow.
+    #[structopt(long, conflicts_with("do-not-validate-input"))]
+    pub max_test_seconds: Option<u64>,
+
+    /// Print statistics about the edits the passes would attempt on this file, then exit
+    ///
+    /// For each pass applying to the file, this shows a histogram of the sizes of
+    /// the edits it would attempt, without running the test. This helps figure out
+    /// why a pass is slow, for instance when it tries thousands of tiny edits. The
+    /// path is relative to the root path.
+    #[structopt(long)]
+    pub dichotomy_stats: Option<PathBuf>,
+
+    /// Only print the statistics of the passes whose name contains this string
+    #[structopt(long, requires("dichotomy-stats"))]
+    pub dichotomy_stats_pass: Option<String>,
+
+    /// How to display the spinners with current job info
+    ///
+    /// Can be `fancy` (the default), `plain` to only use ASCII characters and no
+    /// colors, for instance in CI logs or on terminals without Unicode support, or
+    /// `none` to not display them at all.
+    #[structopt(long, default_value = "fancy")]
+    pub progress_style: ProgressStyle,
 
     /// Do not display the spinners with current job info
+    ///
+    /// This is the same as `--progress-style none`.
     #[structopt(long)]
-    no_progress_bars: bool,
+    pub no_progress_bars: bool,
+
+    /// Log level (or `tracing` filter directives) to use
+    ///
+    /// This takes precedence over both `--verbose` and the `RUST_LOG` environment
+    /// variable. For instance, `--log-level debug` or `--log-level info,tree_sitter_reduce=trace`.
+    /// If neither this, `--verbose` nor `RUST_LOG` are set, the log level is `info`.
+    #[structopt(long)]
+    pub log_level: Option<String>,
+
+    /// Make the logs more verbose
+    ///
+    /// Pass once to see debug logs, and twice to see trace logs. This takes
+    /// precedence over the `RUST_LOG` environment variable.
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
+}
+
+/// How to display the progress bars
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProgressStyle {
+    /// Unicode spinners, with colors
+    Fancy,
+    /// ASCII spinners, without colors
+    Plain,
+    /// No progress bars at all
+    None,
+}
+
+impl FromStr for ProgressStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "fancy" => Ok(ProgressStyle::Fancy),
+            "plain" => Ok(ProgressStyle::Plain),
+            "none" => Ok(ProgressStyle::None),
+            _ => {
+                anyhow::bail!("unknown progress style {s:?}, expected one of fancy, plain or none")
+            }
+        }
+    }
+}
+
+/// Subset of the files to reduce, see `Opt::shard`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shard {
+    /// Index of this shard, starting from 1
+    pub index: usize,
+    /// Total number of shards
+    pub count: usize,
+}
+
+impl Shard {
+    /// Keep only the files that belong to this shard
+    ///
+    /// Files are sorted then dealt round-robin, so that all shards get roughly the
+    /// same number of files.
+    fn filter(&self, mut files: Vec<PathBuf>) -> Vec<PathBuf> {
+        files.sort();
+        files
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| i % self.count == self.index - 1)
+            .map(|(_, f)| f)
+            .collect()
+    }
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (index, count) = s
+            .split_once('/')
+            .with_context(|| format!("shard {s:?} is not in `i/n` format"))?;
+        let index = index
+            .parse()
+            .with_context(|| format!("parsing shard index {index:?}"))?;
+        let count = count
+            .parse()
+            .with_context(|| format!("parsing shard count {count:?}"))?;
+        anyhow::ensure!(
+            (1..=count).contains(&index),
+            "shard index must be between 1 and the number of shards, got {s:?}",
+        );
+        Ok(Shard { index, count })
+    }
 }
 
 impl Opt {
+    /// Options reducing `root_path`, with the same defaults as on the command line
+    pub fn new(root_path: PathBuf, snapshot_directory: PathBuf) -> Self {
+        let arg = |name: &str, value: PathBuf| {
+            let mut arg = OsString::from(format!("--{name}="));
+            arg.push(value);
+            arg
+        };
+        <Opt as structopt::StructOpt>::from_iter_safe([
+            OsString::from("tree-sitter-reduce"),
+            arg("root-path", root_path),
+            arg("snapshot-directory", snapshot_directory),
+        ])
+        .expect("The default options should always be valid")
+    }
+
     pub fn real_root_path(&mut self) -> anyhow::Result<PathBuf> {
         if !self.resume {
             let root = self
@@ -105,47 +494,296 @@ impl Opt {
                 .with_context(|| format!("canonicalizing root path {root:?}"))
         } else {
             let snap_dir = &self.snapshot_directory;
-            let mut snapshots = std::fs::read_dir(snap_dir)
-                .with_context(|| format!("listing snapshot directory {snap_dir:?}"))?
-                .collect::<Result<Vec<_>, _>>()
-                .with_context(|| format!("listing snapshot directory {snap_dir:?}"))?;
-            snapshots.sort_by_key(|s| s.file_name());
-            match snapshots.pop() {
+            match latest_snapshot(snap_dir)? {
                 None => anyhow::bail!("No snapshots found in snapshot directory {snap_dir:?}, but `--resume` was provided"),
-                Some(snapshot) => {
-                    let snap = snapshot.path();
+                Some(snap) => {
                     snap.canonicalize().with_context(|| format!("canonicalizing snapshot path {snap:?}"))
                 }
             }
         }
     }
 
+    pub fn progress_style(&self) -> ProgressStyle {
+        match self.no_progress_bars {
+            true => ProgressStyle::None,
+            false => self.progress_style,
+        }
+    }
+
+    pub fn log_level(&self) -> Option<&str> {
+        match (&self.log_level, self.verbose) {
+            (Some(l), _) => Some(l),
+            (None, 0) => None,
+            (None, 1) => Some("debug"),
+            (None, _) => Some("trace"),
+        }
+    }
+
     pub fn files(
         &self,
         real_root_path: &Path,
         default_list: impl Fn(&Path) -> anyhow::Result<Vec<PathBuf>>,
     ) -> anyhow::Result<Vec<PathBuf>> {
-        match &self.only_files {
-            Some(r) => Ok(r.clone()),
-            None => default_list(&real_root_path),
-        }
+        let files = match &self.only_files {
+            Some(r) => r.clone(),
+            None => default_list(&real_root_path)?,
+        };
+        Ok(match &self.shard {
+            Some(shard) => shard.filter(files),
+            None => files,
+        })
     }
 }
 
+/// Returns the path to the latest snapshot in `snap_dir`, if any
+fn latest_snapshot(snap_dir: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let mut snapshots = std::fs::read_dir(snap_dir)
+        .with_context(|| format!("listing snapshot directory {snap_dir:?}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("listing snapshot directory {snap_dir:?}"))?;
+    snapshots.retain(|s| is_snapshot(&s.file_name()));
+    snapshots.sort_by_key(|s| s.file_name());
+    Ok(snapshots.pop().map(|s| s.path()))
+}
+
+/// Record in `snap_dir` that standalone file `file` is being reduced
+fn write_single_file_record(snap_dir: &Path, file: &Path) -> anyhow::Result<()> {
+    let Some(path) = file.to_str() else {
+        tracing::warn!(
+            "Path {file:?} is not valid UTF-8, resuming will not write the result back to it"
+        );
+        return Ok(());
+    };
+    let record = snap_dir.join(SINGLE_FILE_RECORD);
+    std::fs::write(&record, path).with_context(|| format!("writing {record:?}"))
+}
+
+/// Read the standalone file being reduced, if any, as recorded in `snap_dir`
+fn read_single_file_record(snap_dir: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let record = snap_dir.join(SINGLE_FILE_RECORD);
+    match std::fs::read_to_string(&record) {
+        Ok(path) => Ok(Some(PathBuf::from(path))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("reading {record:?}")),
+    }
+}
+
+/// Copy the latest snapshot of standalone file `file` back to it
+fn write_back_single_file(file: &Path, snap_dir: &Path) -> anyhow::Result<()> {
+    let Some(snapshot) = latest_snapshot(snap_dir)? else {
+        return Ok(());
+    };
+    let (snapshot, _rebuilt) = resolve_snapshot(&snapshot, None)?;
+    let reduced = snapshot.join(
+        file.file_name()
+            .expect("Standalone files always have a file name"),
+    );
+    tracing::info!("Writing reduced file {reduced:?} back to {file:?}");
+    std::fs::copy(&reduced, file)
+        .with_context(|| format!("copying reduced file {reduced:?} back to {file:?}"))?;
+    Ok(())
+}
+
 pub fn run(
+    opt: Opt,
+    filelist: impl Fn(&Path) -> anyhow::Result<Vec<PathBuf>>,
+    test: impl Test,
+    passes: &[Arc<dyn Pass>],
+    polish_passes: &[Arc<dyn Pass>],
+) -> anyhow::Result<()> {
+    let progress = init_env(opt.progress_style(), opt.log_level())?;
+
+    // Setup a ctrl-c handler that will kill us whenever
+    let (killer, kill_trigger) = crossbeam_channel::bounded(3);
+    ctrlc::set_handler(move || {
+        killer
+            .send(())
+            .expect("User asked to kill 3 times the reducer before it had the time to notice")
+    })
+    .context("setting the interruption handler")?;
+
+    run_with(
+        opt,
+        filelist,
+        test,
+        passes,
+        polish_passes,
+        progress,
+        kill_trigger,
+        Box::new(|_| ()),
+    )
+}
+
+/// Print a histogram of the sizes of the edits each pass would attempt on `path`
+fn print_dichotomy_stats(
+    root: &Path,
+    path: &Path,
+    pass_filter: Option<&str>,
+    passes: &[Arc<dyn Pass>],
+    seed: u64,
+    size_aware_dichotomy: bool,
+    kill_trigger: &crossbeam_channel::Receiver<()>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        root.join(path).is_file(),
+        "Cannot find file {path:?} in {root:?}",
+    );
+    for pass in passes.iter().filter(|p| p.applies_to(path)) {
+        let name = format!("{pass:?}");
+        if pass_filter.map_or(false, |f| !name.contains(f)) {
+            continue;
+        }
+        let job = Job {
+            size_aware_dichotomy,
+            ..Job::new(path.to_path_buf(), pass.clone(), seed, u8::MAX / 2)?
+        };
+        let sizes = pass
+            .attempt_sizes(root, &job, kill_trigger)
+            .with_context(|| format!("listing the attempts of pass {name}"))?;
+        let Some(sizes) = sizes else {
+            println!("{name}: unknown attempt sizes");
+            continue;
+        };
+        println!(
+            "{name}: {} attempts, {}B in total",
+            sizes.len(),
+            sizes.iter().sum::<usize>(),
+        );
+        // Bucket the sizes by power of two
+        let mut buckets = BTreeMap::<u32, usize>::new();
+        for size in sizes {
+            *buckets
+                .entry(size.checked_ilog2().map_or(0, |l| l + 1))
+                .or_default() += 1;
+        }
+        for (bucket, count) in buckets {
+            let range = match bucket {
+                0 => String::from("0B"),
+                b => format!("{}-{}B", 1u64 << (b - 1), (1u64 << b) - 1),
+            };
+            println!("  {range:>24} {count:>6} {}", "#".repeat(count.min(50)));
+        }
+    }
+    Ok(())
+}
+
+/// Read the seed from `path`, or write a random one to it if it does not exist
+fn seed_from_file(path: &Path) -> anyhow::Result<u64> {
+    match std::fs::read_to_string(path) {
+        Ok(seed) => seed
+            .trim()
+            .parse()
+            .with_context(|| format!("parsing seed {seed:?} from seed file {path:?}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let seed = rand::random();
+            std::fs::write(path, format!("{seed}\n"))
+                .with_context(|| format!("writing seed to seed file {path:?}"))?;
+            Ok(seed)
+        }
+        Err(e) => Err(e).with_context(|| format!("reading seed file {path:?}")),
+    }
+}
+
+/// Run `verify_command` in `root`, failing if it does not succeed
+fn run_verify_command(verify_command: &Path, root: &Path) -> anyhow::Result<()> {
+    tracing::info!("Running verify command {verify_command:?}");
+    // Relative paths are relative to our working directory, not to `root`, but
+    // bare command names are still looked up in `PATH`
+    let command = verify_command
+        .canonicalize()
+        .unwrap_or_else(|_| verify_command.to_path_buf());
+    let output = std::process::Command::new(command)
+        .current_dir(root)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .with_context(|| format!("running verify command {verify_command:?} in {root:?}"))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "Verify command {verify_command:?} failed in {root:?} ({}), please check that the test environment is properly set up. Its output was:\n---\n{}{}---",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+    Ok(())
+}
+
+/// Run the reducer without touching any process-wide state
+///
+/// Unlike `run`, this sets up neither logging nor an interruption handler, and
+/// does not draw progress bars, so that it can be used as a library. The
+/// reduction is interrupted when a message is received on `kill_trigger`, whose
+/// sender must stay alive until this returns.
+///
+/// `on_event` is called on each reduction, snapshot and on convergence, so that the
+/// caller can follow the reduction as it happens.
+pub fn reduce(
+    opt: Opt,
+    filelist: impl Fn(&Path) -> anyhow::Result<Vec<PathBuf>>,
+    test: impl Test,
+    passes: &[Arc<dyn Pass>],
+    polish_passes: &[Arc<dyn Pass>],
+    kill_trigger: crossbeam_channel::Receiver<()>,
+    on_event: impl 'static + Send + Fn(&ReductionEvent),
+) -> anyhow::Result<()> {
+    let progress =
+        indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    run_with(
+        opt,
+        filelist,
+        test,
+        passes,
+        polish_passes,
+        progress,
+        kill_trigger,
+        Box::new(on_event),
+    )
+}
+
+fn run_with(
     mut opt: Opt,
     filelist: impl Fn(&Path) -> anyhow::Result<Vec<PathBuf>>,
     test: impl Test,
     passes: &[Arc<dyn Pass>],
+    polish_passes: &[Arc<dyn Pass>],
+    progress: indicatif::MultiProgress,
+    kill_trigger: crossbeam_channel::Receiver<()>,
+    on_event: EventCallback,
 ) -> anyhow::Result<()> {
-    let progress = init_env(opt.no_progress_bars)?;
     tracing::trace!("Received options {opt:#?}");
 
     // Handle the arguments
     let root = opt.real_root_path()?;
+    let (root, _rebuilt_snapshot) = match opt.resume {
+        true => resolve_snapshot(&root, opt.tmp_dir.as_deref())?,
+        false => (root, None),
+    };
+    let resumed_single_file = match opt.resume {
+        true => read_single_file_record(&opt.snapshot_directory)?,
+        false => None,
+    };
+    let (root, single_file, _wrapped_file) = match (root.is_file(), resumed_single_file) {
+        (true, _) => {
+            tracing::info!("Root path {root:?} is a file, reducing it on its own");
+            let dir = wrap_single_file(&root, opt.tmp_dir.as_deref())?;
+            (dir.path().to_path_buf(), Some(root), Some(dir))
+        }
+        (false, Some(file)) => {
+            tracing::info!("Resuming the reduction of standalone file {file:?}");
+            (root, Some(file), None)
+        }
+        (false, None) => (root, None, None),
+    };
+    anyhow::ensure!(
+        single_file.is_some() || !test.requires_single_file(),
+        "The test only supports reducing a standalone file, but root path {root:?} is a directory",
+    );
     let files = opt.files(&root, filelist)?;
     let files = files.into_iter().collect::<HashSet<PathBuf>>();
-    let seed = opt.random_seed.unwrap_or_else(rand::random);
+    let seed = match &opt.seed_file {
+        Some(path) => seed_from_file(path)?,
+        None => opt.random_seed.unwrap_or_else(rand::random),
+    };
+    let progress_style = opt.progress_style();
     let snap_dir = opt.snapshot_directory;
 
     // Sanity-checks
@@ -153,6 +791,27 @@ pub fn run(
         !passes.is_empty(),
         "Ill-configured runner: no passes are configured",
     );
+    if let Some(path) = &opt.dichotomy_stats {
+        return print_dichotomy_stats(
+            &root,
+            path,
+            opt.dichotomy_stats_pass.as_deref(),
+            passes,
+            seed,
+            opt.size_aware_dichotomy,
+            &kill_trigger,
+        );
+    }
+    anyhow::ensure!(opt.vote > 0, "The number of votes must be at least 1");
+    anyhow::ensure!(
+        !(opt.changed_only && opt.format_command.is_some()),
+        "Formatting snapshots is not supported when only snapshotting changed files",
+    );
+    anyhow::ensure!(
+        (1..=opt.jobs).contains(&opt.min_jobs),
+        "The minimum number of jobs must be between 1 and the number of jobs ({})",
+        opt.jobs,
+    );
     anyhow::ensure!(
         !files.is_empty(),
         "Cannot find any file to reduce in {root:?}",
@@ -172,33 +831,81 @@ pub fn run(
         })?;
         std::fs::remove_dir(&testdir)
             .with_context(|| format!("removing test directory {testdir:?}"))?;
+        if let (false, Some(file)) = (opt.resume, &single_file) {
+            write_single_file_record(&snap_dir, file)?;
+        }
+    }
+    if let Some(verify_command) = &opt.verify_command {
+        run_verify_command(verify_command, &root)?;
+    }
+    if let Some(tempdir_root) = &opt.tempdir_root {
+        clean_tempdir_root(tempdir_root)?;
     }
-    if opt.snapshot_interval > 300 {
+    if opt.snapshot_interval > 300 && !opt.no_snapshots {
         tracing::warn!("You set snapshot interval to more than 5 minutes.");
         tracing::warn!("This usually slows down the time to receive the results, without getting anything in return");
     }
     if opt.resume && opt.root_path.is_some() {
         tracing::warn!("You provided a root path but asked to resume. The root path will be ignored in favor of the latest snapshot");
     }
+    if opt.round_robin && opt.jobs > 1 {
+        tracing::warn!("You asked for round-robin scheduling with multiple jobs. Which job finishes first will still depend on timing, so the reduction will not be reproducible");
+    }
     if opt.resume && opt.do_not_validate_input {
-        tracing::warn!("You asked to resume without validating the input. This is usually a bad idea, remember that a snapshot could be half-written before the program stopped.");
+        tracing::warn!("You asked to resume without validating the input. This is usually a bad idea, remember that your test could have changed since the snapshot was taken.");
     }
 
     // Actually run
     tracing::info!("Initial seed is < {seed} >. It can be used for reproduction if running with a single worker thread");
     let rng = StdRng::seed_from_u64(seed);
-    Runner::new(
-        root,
-        test,
-        files,
-        passes,
-        snap_dir,
-        Duration::from_secs(opt.snapshot_interval),
-        opt.max_snapshots,
+    let report = opt.report_file.map(|path| Report {
+        path,
+        seed,
+        test: test.description(),
+    });
+    let config = RunnerConfig {
+        converge_after: opt.converge_after,
+        target_size: opt.target_size,
+        snap_dir: snap_dir.clone(),
+        snap_interval: (!opt.no_snapshots).then(|| Duration::from_secs(opt.snapshot_interval)),
+        max_snaps: opt.max_snapshots,
+        changed_only: opt.changed_only,
+        format_command: opt.format_command,
+        pause_file: opt.pause_file,
+        report_parse_errors: opt.report_parse_errors,
+        jobs: opt.jobs,
+        min_jobs: opt.min_jobs,
+        worker_config: WorkerConfig {
+            double_check: opt.double_check,
+            tmp_dir: opt.tmp_dir,
+            tempdir_root: opt.tempdir_root,
+            test_cache: None,
+            git_backup: opt.git_backup,
+            tests_run: Arc::default(),
+            votes: opt.vote,
+            test_retries: opt.test_retries,
+        },
+        test_cache_size: opt.test_cache_size,
+        pass_budget: opt.pass_budget.map(Duration::from_secs),
+        max_attempts_per_job: opt.max_attempts_per_job,
+        size_aware_dichotomy: opt.size_aware_dichotomy,
+        prioritize_largest_files: opt.prioritize_largest_files,
+        round_robin: opt.round_robin,
+        symmetric_files: opt.symmetric_files,
+        progress_style,
+        do_not_validate_input: opt.do_not_validate_input,
+        max_test_duration: opt.max_test_seconds.map(Duration::from_secs),
+        report,
+        selected_files_only: opt.only_files.is_some() || opt.shard.is_some(),
         rng,
-        opt.jobs,
         progress,
-        opt.do_not_validate_input,
-    )?
-    .run()
+        kill_trigger,
+        on_event,
+    };
+    let res = Runner::new(root, test, files, passes, polish_passes, config)
+        .and_then(|runner| runner.run());
+    if let Some(file) = &single_file {
+        write_back_single_file(file, &snap_dir)?;
+    }
+    res
 }
@@ -1,4 +1,10 @@
-use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use fxhash::FxHashMap;
@@ -12,51 +18,235 @@ use rand::{rngs::StdRng, seq::SliceRandom, Rng};
 use tempfile::TempDir;
 
 use crate::{
+    cache::{remove_stale_test_caches, TestCache, TEST_CACHE_PREFIX},
+    event::{EventCallback, ReductionEvent},
     job::{Job, JobResult, JobStatus},
-    util::{copy_dir_contents, copy_to_tempdir, make_progress_bar, BAR_TICK_INTERVAL, WORKDIR},
-    workers::Worker,
-    Pass, Test, TestResult,
+    layers::{layer_base, write_layer},
+    replay::ReplayEdit,
+    report::{ReductionStats, Report},
+    util::{
+        copy_dir_contents, copy_to_tempdir, is_snapshot, make_progress_bar, BAR_TICK_INTERVAL,
+        PARTIAL_SNAPSHOT_PREFIX, RUNNER_TEMPDIR, WORKDIR,
+    },
+    workers::{Worker, WorkerConfig},
+    Pass, ProgressStyle, Test, TestResult,
 };
 
-struct FileInfo {
-    recent_success_rate: u8,
+/// Count the `ERROR` and `MISSING` nodes in the tree rooted at `node`
+fn count_parse_errors(node: &tree_sitter::Node) -> (usize, usize) {
+    if !node.has_error() {
+        return (0, 0);
+    }
+    let mut res = match (node.is_error(), node.is_missing()) {
+        (true, _) => (1, 0),
+        (_, true) => (0, 1),
+        _ => (0, 0),
+    };
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        let (errors, missing) = count_parse_errors(&child);
+        res.0 += errors;
+        res.1 += missing;
+    }
+    res
 }
 
-impl FileInfo {
-    fn new() -> FileInfo {
-        FileInfo {
-            recent_success_rate: u8::MAX / 2,
-        }
+/// How often to check whether the pause file was created or removed
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to log a summary of the reductions found, instead of logging each one
+const REDUCTION_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Moving average of how often recent jobs managed to reduce the input
+///
+/// This goes from `0`, if no recent job succeeded, to `u8::MAX`, if all did.
+#[derive(Clone, Copy)]
+struct SuccessRate(u8);
+
+impl SuccessRate {
+    fn new() -> SuccessRate {
+        SuccessRate(u8::MAX / 2)
     }
 
     // (9 * self + MAX) / 10
     fn record_success(&mut self) {
-        self.recent_success_rate =
-            u8::try_from((self.recent_success_rate as u32 * 9 + u8::MAX as u32) / 10).unwrap();
+        self.0 = u8::try_from((self.0 as u32 * 9 + u8::MAX as u32) / 10).unwrap();
     }
 
     // (9 * self + 0) / 10
     fn record_fail(&mut self) {
-        self.recent_success_rate = u8::try_from(self.recent_success_rate as u32 * 9 / 10).unwrap();
+        self.0 = u8::try_from(self.0 as u32 * 9 / 10).unwrap();
     }
 }
 
+struct FileInfo {
+    recent_success_rate: SuccessRate,
+
+    /// Current size of the file, in bytes
+    size: u64,
+
+    /// Passes that apply to this file
+    passes: Vec<Arc<dyn Pass>>,
+}
+
+impl FileInfo {
+    fn new(size: u64, passes: Vec<Arc<dyn Pass>>) -> FileInfo {
+        FileInfo {
+            recent_success_rate: SuccessRate::new(),
+            size,
+            passes,
+        }
+    }
+}
+
+/// If more than this many workers die within `WORKER_DEATH_WINDOW`, the test is
+/// considered unstable
+const MAX_WORKER_DEATHS: usize = 10;
+const WORKER_DEATH_WINDOW: Duration = Duration::from_secs(60);
+
+/// Configuration of the runner, set up once for the whole reduction
+pub(crate) struct RunnerConfig {
+    /// Consider the reduction converged after this many jobs in a row did not reduce
+    pub(crate) converge_after: Option<usize>,
+
+    /// Stop once the files to reduce total less than this many bytes
+    pub(crate) target_size: Option<u64>,
+
+    /// Directory in which to write the snapshots
+    pub(crate) snap_dir: PathBuf,
+
+    /// `None` if only the final snapshot should be taken
+    pub(crate) snap_interval: Option<Duration>,
+
+    /// Maximum number of snapshots to keep
+    pub(crate) max_snaps: usize,
+
+    /// Only write the files that changed since the previous snapshot, see `layers`
+    pub(crate) changed_only: bool,
+
+    /// Command to run on each reduced file when taking a snapshot
+    pub(crate) format_command: Option<PathBuf>,
+
+    /// Do not start new jobs while this file exists
+    pub(crate) pause_file: Option<PathBuf>,
+
+    /// Warn at the end about the files tree-sitter does not manage to fully parse
+    pub(crate) report_parse_errors: bool,
+
+    /// Number of workers to start with
+    pub(crate) jobs: usize,
+
+    /// Number of workers to keep, even if they die too often
+    pub(crate) min_jobs: usize,
+
+    pub(crate) worker_config: WorkerConfig,
+
+    /// Number of test results to cache, or `0` to disable caching
+    pub(crate) test_cache_size: usize,
+
+    /// See `Job::time_budget`
+    pub(crate) pass_budget: Option<Duration>,
+
+    /// See `Job::max_attempts`
+    pub(crate) max_attempts_per_job: Option<usize>,
+
+    /// See `Job::size_aware_dichotomy`
+    pub(crate) size_aware_dichotomy: bool,
+
+    /// Pick the files to reduce by size rather than by recent success rate
+    pub(crate) prioritize_largest_files: bool,
+
+    /// Go through all (file, pass) pairs in turn, instead of choosing them randomly
+    pub(crate) round_robin: bool,
+
+    /// Replay successful edits on similar files
+    pub(crate) symmetric_files: bool,
+
+    pub(crate) progress_style: ProgressStyle,
+
+    /// Skip checking that the input is interesting before reducing it
+    pub(crate) do_not_validate_input: bool,
+
+    /// Refuse to reduce inputs on which the test takes longer than this
+    pub(crate) max_test_duration: Option<Duration>,
+
+    /// Report to write when exiting, if any
+    pub(crate) report: Option<Report>,
+
+    /// Only some of the files of the tree are being reduced, like with `--shard`
+    ///
+    /// The passes then only run on a file if all the other files they would edit
+    /// along with it are being reduced too.
+    pub(crate) selected_files_only: bool,
+
+    /// Source of randomness for the whole reduction, seeded from the printed seed
+    pub(crate) rng: StdRng,
+
+    pub(crate) progress: indicatif::MultiProgress,
+    pub(crate) kill_trigger: crossbeam_channel::Receiver<()>,
+    pub(crate) on_event: EventCallback,
+}
+
 pub(crate) struct Runner<'a, T> {
     root: TempDir,
     test: Arc<T>,
     // FxHashMap because we want deterministic iteration order, for
     // random-based-on-printed-seed-only iteration order
     files: FxHashMap<PathBuf, FileInfo>,
-    passes: &'a [Arc<dyn Pass>],
+    polish_passes: &'a [Arc<dyn Pass>],
+    pass_success_rates: Vec<(Arc<dyn Pass>, SuccessRate)>,
+    converge_after: Option<usize>,
+    target_size: Option<u64>,
     snap_dir: PathBuf,
-    snap_interval: Duration,
+    /// `None` if only the final snapshot should be taken
+    snap_interval: Option<Duration>,
     max_snaps: usize,
-    workers: Vec<Worker>,
+    /// Only write the files that changed since the previous snapshot, see `layers`
+    changed_only: bool,
+    /// Name of the previous snapshot of this run, and number of layers since the
+    /// last full snapshot
+    last_snapshot: Option<(String, usize)>,
+    /// Files that changed since the previous snapshot
+    changed_since_snapshot: HashSet<PathBuf>,
+    format_command: Option<PathBuf>,
+    report_parse_errors: bool,
+    workers: Vec<(WorkerId, Worker)>,
+    next_worker_id: u64,
+    pause_file: Option<PathBuf>,
+    /// Workers that are not running any job because the reduction is paused
+    idle_workers: Vec<WorkerId>,
+    min_jobs: usize,
+    worker_deaths: VecDeque<Instant>,
+    worker_config: WorkerConfig,
+    test_cache_path: Option<PathBuf>,
     kill_trigger: crossbeam_channel::Receiver<()>,
     rng: StdRng,
+    pass_budget: Option<Duration>,
+    max_attempts_per_job: Option<usize>,
+    size_aware_dichotomy: bool,
+    prioritize_largest_files: bool,
+    initial_total_size: u64,
+    /// Index of the next (file, pass) pair to try, if scheduling in round-robin
+    round_robin: Option<usize>,
+    /// Replay successful edits on similar files
+    symmetric_files: bool,
+    /// Jobs replaying successful edits, to run before choosing new jobs
+    replays: VecDeque<Job>,
+    /// Number of bytes removed by each pass so far
+    removed_by_pass: FxHashMap<String, u64>,
+    /// Report to write when exiting, if any
+    report: Option<Report>,
+    /// Time of the first reduction not logged yet, and number of such reductions
+    unlogged_reductions: Option<(Instant, usize)>,
+    on_event: EventCallback,
 }
 
-struct WorkerIdx(usize);
+/// Identifier of a worker, that stays the same for the whole life of the worker
+///
+/// This is unlike its index in `Runner::workers`, that changes whenever other
+/// workers are removed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct WorkerId(u64);
 
 impl<'a, T: Test> Runner<'a, T> {
     pub(crate) fn new(
@@ -64,35 +254,130 @@ impl<'a, T: Test> Runner<'a, T> {
         test: T,
         files: HashSet<PathBuf>,
         passes: &'a [Arc<dyn Pass>],
-        snap_dir: PathBuf,
-        snap_interval: Duration,
-        max_snaps: usize,
-        rng: StdRng,
-        jobs: usize,
-        progress: indicatif::MultiProgress,
-        do_not_validate_input: bool,
+        polish_passes: &'a [Arc<dyn Pass>],
+        config: RunnerConfig,
     ) -> anyhow::Result<Self> {
-        // Setup a ctrl-c handler that will kill us whenever
-        let (killer, kill_trigger) = crossbeam_channel::bounded(3);
-        ctrlc::set_handler(move || {
-            killer
-                .send(())
-                .expect("User asked to kill 3 times the reducer before it had the time to notice")
-        })
-        .context("setting the interruption handler")?;
+        let RunnerConfig {
+            converge_after,
+            target_size,
+            snap_dir,
+            snap_interval,
+            max_snaps,
+            changed_only,
+            format_command,
+            pause_file,
+            report_parse_errors,
+            jobs,
+            min_jobs,
+            mut worker_config,
+            test_cache_size,
+            pass_budget,
+            max_attempts_per_job,
+            size_aware_dichotomy,
+            prioritize_largest_files,
+            round_robin,
+            symmetric_files,
+            progress_style,
+            do_not_validate_input,
+            max_test_duration,
+            report,
+            selected_files_only,
+            rng,
+            progress,
+            kill_trigger,
+            on_event,
+        } = config;
+
+        // Figure out which passes apply to which file
+        let mut file_infos = FxHashMap::default();
+        for f in &files {
+            let mut applicable = Vec::new();
+            for pass in passes.iter().filter(|p| p.applies_to(f)) {
+                // Other invocations may be reducing the files left out, eg. other shards
+                if selected_files_only {
+                    let extra_paths = pass
+                        .extra_paths(&root, f)
+                        .with_context(|| format!("listing the files edited by pass {pass:?}"))?;
+                    if let Some(other) = extra_paths.iter().find(|p| !files.contains(*p)) {
+                        tracing::debug!("Not running pass {pass:?} on {f:?}, as it would edit {other:?} that is not being reduced");
+                        continue;
+                    }
+                }
+                applicable.push(pass.clone());
+            }
+            if applicable.is_empty() {
+                tracing::warn!("No pass applies to file {f:?}, it will not be reduced");
+                continue;
+            }
+            let path = root.join(&f);
+            let size = std::fs::metadata(&path)
+                .with_context(|| format!("reading metadata of file to reduce {path:?}"))?
+                .len();
+            file_infos.insert(f.clone(), FileInfo::new(size, applicable));
+        }
+        anyhow::ensure!(
+            !file_infos.is_empty(),
+            "No pass applies to any of the files to reduce"
+        );
+        let initial_total_size = file_infos.values().map(|i| i.size).sum();
+
+        // Setup the test cache, reusing the results of the previous runs if any
+        let mut test_cache_path = None;
+        if test_cache_size > 0 {
+            let cache = TestCache::new(file_infos.keys().cloned().collect(), test_cache_size);
+            if let Some(identity) = test.identity() {
+                let path = snap_dir.join(format!("{TEST_CACHE_PREFIX}{identity:016x}"));
+                remove_stale_test_caches(&snap_dir, &path)?;
+                cache.load(&path)?;
+                test_cache_path = Some(path);
+            }
+            worker_config.test_cache = Some(Arc::new(cache));
+        }
 
         // Copy the target directory to a tempdir
         let mut this = Runner {
-            root: copy_to_tempdir(&root)?,
+            root: match &worker_config.tempdir_root {
+                Some(tempdir_root) => {
+                    copy_to_tempdir(&root, Some(RUNNER_TEMPDIR), Some(tempdir_root))?
+                }
+                None => copy_to_tempdir(&root, None, worker_config.tmp_dir.as_deref())?,
+            },
             test: Arc::new(test),
-            files: files.into_iter().map(|f| (f, FileInfo::new())).collect(),
-            passes,
+            files: file_infos,
+            polish_passes,
+            pass_success_rates: Vec::new(),
+            converge_after,
+            target_size,
             snap_dir,
             snap_interval,
             max_snaps,
+            changed_only,
+            last_snapshot: None,
+            changed_since_snapshot: HashSet::new(),
+            format_command,
             workers: Vec::with_capacity(jobs),
+            next_worker_id: 0,
+            pause_file,
+            report_parse_errors,
+            idle_workers: Vec::new(),
+            min_jobs,
+            worker_deaths: VecDeque::new(),
+            worker_config,
+            test_cache_path,
             kill_trigger,
             rng,
+            pass_budget,
+            max_attempts_per_job,
+            size_aware_dichotomy,
+            prioritize_largest_files,
+            initial_total_size,
+            round_robin: round_robin.then_some(0),
+            symmetric_files,
+            replays: VecDeque::new(),
+            removed_by_pass: FxHashMap::default(),
+            report,
+            unlogged_reductions: None,
+            on_event,
         };
 
         // Check that the provided test actually returns true on the initial input
@@ -102,9 +387,11 @@ impl<'a, T: Test> Runner<'a, T> {
         if do_not_validate_input {
             tracing::warn!("Not validating the target directory. Note that validation does not usually make a reduction take significantly longer, but does avoid long useless waits due to malformed input.");
         } else {
-            let bar = make_progress_bar();
+            let bar = make_progress_bar(progress_style);
             bar.enable_steady_tick(BAR_TICK_INTERVAL);
             bar.set_message("Checking that the provided target directory is interesting");
+            this.worker_config.tests_run.fetch_add(1, Ordering::Relaxed);
+            let start = Instant::now();
             let res = this.test.test_interesting(
                 &this.root.path().join(WORKDIR),
                 &this.kill_trigger,
@@ -118,72 +405,505 @@ impl<'a, T: Test> Runner<'a, T> {
                 }
                 TestResult::Interrupted => anyhow::bail!("Killed by user"),
             }
+            let elapsed = start.elapsed();
+            if let Some(max) = max_test_duration.filter(|max| elapsed > *max) {
+                anyhow::bail!("The test took {:.1} seconds on the provided target directory, more than the maximum of {} seconds. The reduction would run it thousands of times, please make it faster first, for instance by only building what is needed to reproduce the issue", elapsed.as_secs_f64(), max.as_secs());
+            }
             bar.finish_and_clear();
             tracing::info!("The target directory was interesting, starting reducing…");
         }
 
         for _ in 0..jobs {
-            this.spawn_worker(progress.add(make_progress_bar()))?;
+            this.spawn_worker(progress.add(make_progress_bar(progress_style)))?;
         }
 
         Ok(this)
     }
 
+    fn new_worker(&mut self, progress: ProgressBar) -> anyhow::Result<(WorkerId, Worker)> {
+        let id = WorkerId(self.next_worker_id);
+        self.next_worker_id += 1;
+        let worker = Worker::new(
+            id.0,
+            self.root.path(),
+            self.test.clone(),
+            progress,
+            self.worker_config.clone(),
+        )
+        .context("spinning up a worker")?;
+        Ok((id, worker))
+    }
+
     fn spawn_worker(&mut self, progress: ProgressBar) -> anyhow::Result<()> {
-        let worker = Worker::new(self.root.path(), self.test.clone(), progress)
-            .context("spinning up a worker")?;
-        self.workers.push(worker);
-        self.send_job_to(WorkerIdx(self.workers.len() - 1))?;
+        let (id, worker) = self.new_worker(progress)?;
+        self.workers.push((id, worker));
+        self.send_job_to(id)?;
         Ok(())
     }
 
-    fn send_job_to(&mut self, worker: WorkerIdx) -> anyhow::Result<()> {
-        let (relpath, info) = self
-            .files
+    fn worker(&self, id: WorkerId) -> &Worker {
+        self.workers
             .iter()
-            .skip(self.rng.gen_range(0..self.files.len()))
-            .next()
-            .unwrap();
-        let pass = self.passes.choose(&mut self.rng).unwrap().clone();
+            .find(|(w, _)| *w == id)
+            .map(|(_, w)| w)
+            .unwrap_or_else(|| panic!("Worker {id:?} is not running"))
+    }
+
+    fn take_worker(&mut self, id: WorkerId) -> Worker {
+        let idx = self
+            .workers
+            .iter()
+            .position(|(w, _)| *w == id)
+            .unwrap_or_else(|| panic!("Worker {id:?} is not running"));
+        self.workers.swap_remove(idx).1
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_file.as_ref().map_or(false, |p| p.exists())
+    }
+
+    fn send_job_to(&mut self, worker: WorkerId) -> anyhow::Result<()> {
+        if let Some(pause_file) = self.pause_file.as_ref().filter(|p| p.exists()) {
+            if self.idle_workers.is_empty() {
+                tracing::info!(
+                    "Pause file {pause_file:?} exists, pausing once the running jobs are done"
+                );
+            }
+            self.worker(worker)
+                .progress()
+                .set_message(format!("Paused, remove {pause_file:?} to resume"));
+            self.idle_workers.push(worker);
+            return Ok(());
+        }
+        if let Some(job) = self.replays.pop_front() {
+            return self.worker(worker).submit(job);
+        }
+        let (relpath, info, pass) = match self.round_robin {
+            Some(next) => {
+                let pairs = self
+                    .files
+                    .iter()
+                    .flat_map(|(path, info)| info.passes.iter().map(move |p| (path, info, p)))
+                    .collect::<Vec<_>>();
+                self.round_robin = Some((next + 1) % pairs.len());
+                pairs[next % pairs.len()]
+            }
+            None => {
+                let chosen = match self.prioritize_largest_files {
+                    true => self.choose_file_by_size(),
+                    false => None,
+                };
+                let (relpath, info) = match chosen {
+                    Some(relpath) => self.files.get_key_value(&relpath).unwrap(),
+                    None => self
+                        .files
+                        .iter()
+                        .skip(self.rng.gen_range(0..self.files.len()))
+                        .next()
+                        .unwrap(),
+                };
+                (relpath, info, info.passes.choose(&mut self.rng).unwrap())
+            }
+        };
+        let pass = pass.clone();
         let seed = self.rng.gen();
-        let recent_success_rate = info.recent_success_rate;
-        let job = Job::new(relpath.clone(), pass, seed, recent_success_rate)?;
-        self.workers[worker.0].submit(job)?;
+        let recent_success_rate = info.recent_success_rate.0;
+        let relpath = relpath.clone();
+        let pass_success_rate = self.pass_success_rate(&pass).0;
+        let mut job = Job {
+            pass_success_rate,
+            time_budget: self.pass_budget,
+            max_attempts: self.max_attempts_per_job,
+            size_aware_dichotomy: self.size_aware_dichotomy,
+            ..Job::new(relpath, pass, seed, recent_success_rate)?
+        };
+        job.extra_paths = self.extra_paths(&job)?;
+        self.worker(worker).submit(job)?;
+        Ok(())
+    }
+
+    /// List the files other than `job.path` that `job` may edit, see `Pass::extra_paths`
+    fn extra_paths(&self, job: &Job) -> anyhow::Result<Vec<PathBuf>> {
+        job.pass
+            .extra_paths(&self.root.path().join(WORKDIR), &job.path)
+            .with_context(|| format!("listing the files edited by pass {job:?}"))
+    }
+
+    /// Send jobs to the idle workers, if the reduction is not paused any longer
+    fn resume_idle_workers(&mut self) -> anyhow::Result<()> {
+        if self.idle_workers.is_empty() || self.is_paused() {
+            return Ok(());
+        }
+        tracing::info!("Pause file was removed, resuming");
+        for worker in std::mem::take(&mut self.idle_workers) {
+            self.send_job_to(worker)?;
+        }
+        Ok(())
+    }
+
+    /// Randomly choose a file, weighted by both its size and its recent success rate
+    ///
+    /// The size counts more at the beginning of the reduction, and the recent success
+    /// rate more and more as the total size decreases. Returns `None` if all the
+    /// weights are zero.
+    fn choose_file_by_size(&mut self) -> Option<PathBuf> {
+        let total_size = self.total_size();
+        let total_rate = self
+            .files
+            .values()
+            .map(|i| i.recent_success_rate.0 as u64)
+            .sum::<u64>();
+        let size_weight = total_size as f64 / self.initial_total_size.max(1) as f64;
+        let files = self.files.iter().collect::<Vec<_>>();
+        files
+            .choose_weighted(&mut self.rng, |(_, i)| {
+                size_weight * i.size as f64 / total_size.max(1) as f64
+                    + (1. - size_weight) * i.recent_success_rate.0 as f64 / total_rate.max(1) as f64
+            })
+            .ok()
+            .map(|(path, _)| PathBuf::clone(path))
+    }
+
+    fn pass_success_rate(&mut self, pass: &Arc<dyn Pass>) -> &mut SuccessRate {
+        let idx = match self
+            .pass_success_rates
+            .iter()
+            .position(|(p, _)| Arc::ptr_eq(p, pass))
+        {
+            Some(idx) => idx,
+            None => {
+                self.pass_success_rates
+                    .push((pass.clone(), SuccessRate::new()));
+                self.pass_success_rates.len() - 1
+            }
+        };
+        &mut self.pass_success_rates[idx].1
+    }
+
+    /// Current total size of the files being reduced
+    fn total_size(&self) -> u64 {
+        self.files.values().map(|i| i.size).sum()
+    }
+
+    /// Refresh the recorded sizes of the files being reduced that `job` edited, after
+    /// it reduced them
+    fn update_sizes(&mut self, job: &Job) -> anyhow::Result<()> {
+        for path in job.paths() {
+            // Passes can also edit files that are not being reduced, like manifests
+            if self.files.contains_key(path) {
+                self.update_size(format!("{:?}", job.pass), path)?;
+            }
+        }
         Ok(())
     }
 
+    /// Refresh the recorded size of the file at `path`, after `pass` reduced it
+    ///
+    /// If `pass` removed the file, it is no longer reduced.
+    fn update_size(&mut self, pass: String, path: &Path) -> anyhow::Result<()> {
+        let real_path = self.root.path().join(WORKDIR).join(path);
+        let size = match std::fs::metadata(&real_path) {
+            Ok(metadata) => Some(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("reading metadata of reduced file {real_path:?}"))
+            }
+        };
+        let old_size = match size {
+            Some(size) => std::mem::replace(&mut self.files.get_mut(path).unwrap().size, size),
+            None => {
+                tracing::debug!("File {path:?} was removed, no longer reducing it");
+                self.replays.retain(|j| !j.paths().any(|p| p == path));
+                self.files.remove(path).unwrap().size
+            }
+        };
+        let size = size.unwrap_or(0);
+        *self.removed_by_pass.entry(pass.clone()).or_default() += old_size.saturating_sub(size);
+        (self.on_event)(&ReductionEvent::Reduced {
+            pass,
+            path: path.to_path_buf(),
+            old_size,
+            new_size: size,
+        });
+        Ok(())
+    }
+
+    /// Reduce until convergence, or until the files are smaller than the target size
+    ///
+    /// The polish passes are only run in the former case.
     pub(crate) fn run(mut self) -> anyhow::Result<()> {
-        let mut next_snap = std::time::Instant::now() + self.snap_interval;
+        let res = self.reduce();
+        self.log_reductions(true);
+        if res.is_err() && self.snap_interval.is_none() {
+            // No snapshot was taken yet, so take one to not lose the reductions
+            self.snapshot()?;
+        }
+        if let Some(report) = &self.report {
+            // Interrupted reductions are reported too, as they usually made progress
+            let written = report.write(ReductionStats {
+                initial_size: self.initial_total_size,
+                final_size: self.total_size(),
+                tests_run: self.worker_config.tests_run.load(Ordering::Relaxed),
+                removed_by_pass: self
+                    .removed_by_pass
+                    .iter()
+                    .filter(|(_, removed)| **removed > 0)
+                    .map(|(pass, removed)| (pass.clone(), *removed))
+                    .collect(),
+            });
+            return res.and(written);
+        }
+        res
+    }
+
+    fn reduce(&mut self) -> anyhow::Result<()> {
+        let mut next_snap = self.snap_interval.map(|i| Instant::now() + i);
         let mut did_reduce = false;
-        loop {
+        let mut jobs_since_reduction = 0;
+        let polish_worker = loop {
             // Note: `snap_interval` can be equal to 0, so if we did not have this match
             // we would be busy-looping.
-            let worker = match did_reduce {
-                true => self.wait_for_worker(Some(next_snap))?,
-                false => self.wait_for_worker(None)?,
-            };
+            let mut deadline = next_snap.filter(|_| did_reduce);
+            if self.pause_file.is_some() {
+                // Regularly check whether the pause file was created or removed
+                let poll = Instant::now() + PAUSE_POLL_INTERVAL;
+                deadline = Some(deadline.map_or(poll, |d| d.min(poll)));
+            }
+            if let Some((first, _)) = self.unlogged_reductions {
+                let log = first + REDUCTION_LOG_INTERVAL;
+                deadline = Some(deadline.map_or(log, |d| d.min(log)));
+            }
+            let worker = self.wait_for_worker(deadline)?;
+            self.log_reductions(false);
             if let Some((worker, pass_status)) = worker {
                 did_reduce |= pass_status.did_reduce();
+                match pass_status.did_reduce() {
+                    true => jobs_since_reduction = 0,
+                    false => jobs_since_reduction += 1,
+                }
+                if let Some(target_size) = self.target_size {
+                    let total_size = self.total_size();
+                    if total_size < target_size {
+                        self.log_reductions(true);
+                        tracing::info!("Total size of the files is {total_size} bytes, below the target size of {target_size} bytes, stopping the reduction");
+                        self.stop_other_workers(worker)?;
+                        return self.finish();
+                    }
+                }
+                if self.files.is_empty() {
+                    self.log_reductions(true);
+                    tracing::info!("All the files to reduce were removed, stopping the reduction");
+                    self.stop_other_workers(worker)?;
+                    return self.finish();
+                }
+                if self.converge_after == Some(jobs_since_reduction) {
+                    self.log_reductions(true);
+                    tracing::info!("No reduction found in the last {jobs_since_reduction} jobs, considering the reduction converged");
+                    (self.on_event)(&ReductionEvent::Converged {
+                        total_size: self.total_size(),
+                    });
+                    break self.stop_other_workers(worker)?;
+                }
                 self.send_job_to(worker)?;
             }
-            if did_reduce && std::time::Instant::now() >= next_snap {
+            self.resume_idle_workers()?;
+            if did_reduce && next_snap.map_or(false, |s| Instant::now() >= s) {
                 // We have passed next snap time!
                 self.snapshot()?;
-                next_snap = std::time::Instant::now() + self.snap_interval;
+                next_snap = self.snap_interval.map(|i| Instant::now() + i);
                 did_reduce = false;
             }
+        };
+        self.polish(polish_worker)?;
+        self.finish()
+    }
+
+    /// Log how many reductions were found since the last such log
+    ///
+    /// Unless `force` is set, this waits for `REDUCTION_LOG_INTERVAL` after the
+    /// first of these reductions, so that bursts of reductions get summarized in
+    /// a single line.
+    fn log_reductions(&mut self, force: bool) {
+        let Some((first, count)) = self.unlogged_reductions else {
+            return;
+        };
+        let elapsed = first.elapsed();
+        if !force && elapsed < REDUCTION_LOG_INTERVAL {
+            return;
+        }
+        let reductions = match count {
+            1 => "reduction",
+            _ => "reductions",
+        };
+        tracing::info!(
+            "Found {count} {reductions} in the last {:.1} seconds, total size is now {} bytes",
+            elapsed.as_secs_f64(),
+            self.total_size(),
+        );
+        self.unlogged_reductions = None;
+    }
+
+    /// Take the final snapshot, and report the files that ended up unparseable
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.snapshot()?;
+        if self.report_parse_errors {
+            self.report_parse_errors()?;
+        }
+        Ok(())
+    }
+
+    /// Warn about the files that tree-sitter does not manage to fully parse
+    ///
+    /// Tree-sitter passes cannot do much with the parts of such files that are
+    /// covered by `ERROR` nodes, which can explain why they stopped reducing.
+    fn report_parse_errors(&self) -> anyhow::Result<()> {
+        let workdir = self.root.path().join(WORKDIR);
+        let mut files = self.files.iter().collect::<Vec<_>>();
+        files.sort_by_key(|(path, _)| *path);
+        let mut unparseable = 0;
+        for (path, info) in files {
+            let Some(language) = info.passes.iter().find_map(|p| p.language()) else {
+                continue;
+            };
+            let real_path = workdir.join(path);
+            let contents = std::fs::read(&real_path)
+                .with_context(|| format!("reading reduced file {real_path:?}"))?;
+            let mut parser = tree_sitter::Parser::new();
+            parser
+                .set_language(language)
+                .expect("Failed to make a parser with configured language");
+            let Some(tree) = parser.parse(&contents, None) else {
+                tracing::warn!("Failed to parse {path:?} at the end of the reduction");
+                unparseable += 1;
+                continue;
+            };
+            let (errors, missing) = count_parse_errors(&tree.root_node());
+            if errors + missing > 0 {
+                tracing::warn!("{path:?} has {errors} ERROR and {missing} MISSING nodes, tree-sitter passes could not reduce these parts");
+                unparseable += 1;
+            }
+        }
+        match unparseable {
+            0 => tracing::info!("All the files parse without errors"),
+            n => tracing::warn!("Found parse errors in {n} of the reduced files"),
+        }
+        Ok(())
+    }
+
+    /// Interrupt all the workers except `keep`, that must not have a job running
+    ///
+    /// Returns `keep`, and leaves no worker in `self.workers`. Reductions found by
+    /// the other workers while they were being interrupted are discarded.
+    fn stop_other_workers(&mut self, keep: WorkerId) -> anyhow::Result<Worker> {
+        let keep = self.take_worker(keep);
+        for id in std::mem::take(&mut self.idle_workers) {
+            self.take_worker(id).recover_bar().finish_and_clear();
+        }
+        for (_, w) in &self.workers {
+            w.send_kill();
+        }
+        tracing::trace!("Waiting for all other workers to reply to the kill message");
+        while !self.workers.is_empty() {
+            let mut sel = crossbeam_channel::Select::new();
+            for (_, w) in self.workers.iter() {
+                sel.recv(w.get_receiver());
+            }
+            sel.recv(&self.kill_trigger);
+            let oper = sel.select();
+            let w = oper.index();
+            if w == self.workers.len() {
+                let _ = oper.recv(&self.kill_trigger);
+                anyhow::bail!("Killed by user");
+            }
+            if let Ok(JobResult {
+                res: Ok(JobStatus::Reduced(desc)),
+                ..
+            }) = oper.recv(self.workers[w].1.get_receiver())
+            {
+                tracing::debug!("Discarding reduction found while stopping workers: {desc}");
+            }
+            self.workers.swap_remove(w);
+        }
+        Ok(keep)
+    }
+
+    /// Run each polish pass once on each file, in a deterministic order
+    ///
+    /// `worker` must be idle and have a checkout equal to the current best state.
+    fn polish(&mut self, mut worker: Worker) -> anyhow::Result<()> {
+        if self.polish_passes.is_empty() {
+            return Ok(());
+        }
+        tracing::info!("Running the polish passes");
+        let files = self.files.keys().cloned().collect::<Vec<_>>();
+        for path in files {
+            for pass in self.polish_passes.iter().filter(|p| p.applies_to(&path)) {
+                // Previous polish jobs can have removed the file
+                let Some(info) = self.files.get(&path) else {
+                    break;
+                };
+                let recent_success_rate = info.recent_success_rate.0;
+                let pass_success_rate = self.pass_success_rate(pass).0;
+                // Polish passes only run once, so they must try the smallest edits too,
+                // and thus leave `size_aware_dichotomy` unset
+                let mut job = Job {
+                    pass_success_rate,
+                    time_budget: self.pass_budget,
+                    max_attempts: self.max_attempts_per_job,
+                    ..Job::new(
+                        path.clone(),
+                        pass.clone(),
+                        self.rng.gen(),
+                        recent_success_rate,
+                    )?
+                };
+                job.extra_paths = self.extra_paths(&job)?;
+                worker.submit(job)?;
+                let mut sel = crossbeam_channel::Select::new();
+                sel.recv(worker.get_receiver());
+                sel.recv(&self.kill_trigger);
+                let oper = sel.select();
+                if oper.index() == 1 {
+                    oper.recv(&self.kill_trigger)
+                        .expect("Kill trigger should never disconnect at all");
+                    worker.send_kill();
+                    anyhow::bail!("Killed by the user");
+                }
+                match oper
+                    .recv(worker.get_receiver())
+                    .expect("Workers should never disconnect first")
+                {
+                    JobResult {
+                        job,
+                        res: Ok(JobStatus::Reduced(desc)),
+                    } => {
+                        tracing::info!("Polish pass successfully reduced the input: {desc}");
+                        self.save_reduction(worker.rootdir())?;
+                        self.changed_since_snapshot
+                            .extend(job.paths().map(Path::to_path_buf));
+                        self.update_sizes(&job)?;
+                    }
+                    JobResult { res: Ok(_), .. } => (),
+                    JobResult { job, res: Err(e) } => {
+                        tracing::error!("Worker died while processing a polish job! Starting a new worker…\nJob: {job:?}\nError:\n---\n{e:?}\n---");
+                        worker = self.new_worker(worker.recover_bar())?.1;
+                    }
+                }
+            }
         }
+        Ok(())
     }
 
     /// Returns Some((worker id, job result)) if a worker finished, None otherwise
     fn wait_for_worker(
         &mut self,
         deadline: Option<std::time::Instant>,
-    ) -> anyhow::Result<Option<(WorkerIdx, JobStatus)>> {
+    ) -> anyhow::Result<Option<(WorkerId, JobStatus)>> {
         loop {
             // Find the first worker with a message
             let mut sel = crossbeam_channel::Select::new();
-            for w in &self.workers {
+            for (_, w) in &self.workers {
                 sel.recv(w.get_receiver());
             }
             sel.recv(&self.kill_trigger);
@@ -200,22 +920,27 @@ impl<'a, T: Test> Runner<'a, T> {
             if w == self.workers.len() {
                 oper.recv(&self.kill_trigger)
                     .expect("Kill trigger should never disconnect at all");
-                for w in self.workers.drain(..) {
+                for (_, w) in self.workers.drain(..) {
                     w.send_kill();
                 }
                 anyhow::bail!("Killed by the user");
             }
 
             // If not, read its message and act upon it
-            let w = WorkerIdx(w);
+            let w = self.workers[w].0;
             match oper
-                .recv(self.workers[w.0].get_receiver())
+                .recv(self.worker(w).get_receiver())
                 .expect("Workers should never disconnect first")
             {
                 JobResult { job, res: Ok(res) } => {
                     match &res {
                         JobStatus::Reduced(desc) => {
-                            tracing::info!("Job successfully reduced the input: {desc}")
+                            // Reductions can come in bursts, so they are logged in batches
+                            tracing::trace!("Job successfully reduced the input: {desc}");
+                            let (_, count) = self
+                                .unlogged_reductions
+                                .get_or_insert_with(|| (Instant::now(), 0));
+                            *count += 1;
                         }
                         JobStatus::DidNotReduce => {
                             tracing::trace!("Job did not reduce the input: {:?} {:?}", job.pass, job.path)
@@ -225,92 +950,209 @@ impl<'a, T: Test> Runner<'a, T> {
                         }
                         JobStatus::Interrupted => panic!("Got interrupted job result even though that should happen only after the runner itself is stopped"),
                     }
-                    let w = self.handle_result(w, job, &res)?;
+                    self.handle_result(w, job, &res)?;
                     return Ok(Some((w, res)));
                 }
                 JobResult { job, res: Err(e) } => {
                     tracing::error!("Worker died while processing a job! Starting a new worker…\nJob: {job:?}\nError:\n---\n{e:?}\n---");
-                    let worker = self.workers.swap_remove(w.0);
-                    self.spawn_worker(worker.recover_bar())?;
+                    let worker = self.take_worker(w);
+                    self.handle_worker_death(worker, e)?;
                 }
             }
         }
     }
 
-    /// Note that while handling the result, the worker order can change
-    ///
-    /// So this returns the new WorkerIdx for `worker`.
-    fn handle_result(
-        &mut self,
-        worker: WorkerIdx,
-        job: Job,
-        res: &JobStatus,
-    ) -> anyhow::Result<WorkerIdx> {
+    /// Respawn a worker that died, unless workers have been dying too often
+    fn handle_worker_death(&mut self, worker: Worker, error: anyhow::Error) -> anyhow::Result<()> {
+        let now = Instant::now();
+        self.worker_deaths.push_back(now);
+        while let Some(&death) = self.worker_deaths.front() {
+            if now.duration_since(death) <= WORKER_DEATH_WINDOW {
+                break;
+            }
+            self.worker_deaths.pop_front();
+        }
+        if self.worker_deaths.len() <= MAX_WORKER_DEATHS {
+            return self.spawn_worker(worker.recover_bar());
+        }
+        let deaths = self.worker_deaths.len();
+        let window = WORKER_DEATH_WINDOW.as_secs();
+        if self.workers.len() < self.min_jobs {
+            for (_, w) in self.workers.drain(..) {
+                w.send_kill();
+            }
+            anyhow::bail!("Workers died {deaths} times in the last {window} seconds, even with only {} of them running. Your test is probably unstable, please check that it reliably returns the same result for the same input.\nLast error:\n---\n{error:?}\n---", self.min_jobs);
+        }
+        tracing::warn!(
+            "Workers died {deaths} times in the last {window} seconds, your test is probably unstable. Not restarting the worker, {} workers are left running",
+            self.workers.len(),
+        );
+        worker.recover_bar().finish_and_clear();
+        Ok(())
+    }
+
+    /// Whether `job` replays an edit found by another job, instead of running one of
+    /// the passes of its file
+    fn is_replay(&self, job: &Job) -> bool {
+        !self.files[&job.path]
+            .passes
+            .iter()
+            .any(|p| Arc::ptr_eq(p, &job.pass))
+    }
+
+    fn handle_result(&mut self, worker: WorkerId, job: Job, res: &JobStatus) -> anyhow::Result<()> {
+        if self.is_replay(&job) {
+            // Replays say nothing about how well their pass or files reduce
+            if res.did_reduce() {
+                self.handle_reduction(worker, &job)?;
+            }
+            return Ok(());
+        }
         match res {
             JobStatus::Reduced(_) => {
-                self.files.get_mut(&job.path).unwrap().record_success();
-                return self.handle_reduction(worker, job);
+                self.files
+                    .get_mut(&job.path)
+                    .unwrap()
+                    .recent_success_rate
+                    .record_success();
+                self.pass_success_rate(&job.pass).record_success();
+                // Edits spanning multiple files are too specific to be replayed
+                if self.symmetric_files && job.extra_paths.is_empty() {
+                    let files = self.files.keys().cloned().collect::<Vec<_>>();
+                    let replay = ReplayEdit::job_for(
+                        &job,
+                        &self.root.path().join(WORKDIR),
+                        &self.worker(worker).rootdir().join(WORKDIR),
+                        &files,
+                        self.rng.gen(),
+                    )
+                    .context("looking for files similar to the reduced one")?;
+                    self.replays.extend(replay);
+                }
+                return self.handle_reduction(worker, &job);
             }
             JobStatus::DidNotReduce => {
-                self.files.get_mut(&job.path).unwrap().record_fail();
+                self.files
+                    .get_mut(&job.path)
+                    .unwrap()
+                    .recent_success_rate
+                    .record_fail();
+                self.pass_success_rate(&job.pass).record_fail();
             }
             // TODO: do something to avoid trying this pass again on the same file just after?
             JobStatus::PassFailed(_) => (),
             JobStatus::Interrupted => panic!("Got interrupted job result even though that should happen only after the runner itself is stopped"),
         }
-        Ok(worker)
+        Ok(())
     }
 
-    fn handle_reduction(&mut self, worker: WorkerIdx, _job: Job) -> anyhow::Result<WorkerIdx> {
-        // TODO: try to intelligently merge successful reductions? that's what _job would be for
+    fn handle_reduction(&mut self, worker: WorkerId, job: &Job) -> anyhow::Result<()> {
+        // TODO: try to intelligently merge successful reductions? that's what job would be for
         tracing::trace!("Handling reduction");
-        // Retrieve the worker's successful reduction to "current best" state
-        let my_dir = self.root.path();
-        let my_workdir = my_dir.join(WORKDIR);
-        let workerdir = self.workers[worker.0].rootdir();
-        std::fs::remove_dir_all(&my_workdir)
-            .with_context(|| format!("removing \"current status\" path {my_workdir:?}"))?;
-        fs_extra::dir::copy(
-            workerdir,
-            &my_dir,
-            &fs_extra::dir::CopyOptions::default().content_only(true),
-        )
-        .with_context(|| {
-            format!("copying successful reduction from {workerdir:?} to {my_dir:?}")
-        })?;
-        // Restart other workers so they actually take advantage of it
+        self.save_reduction(self.worker(worker).rootdir())?;
+        // Before handing out new jobs, so that none of them is on a removed file
+        self.update_sizes(job)?;
+        let reduced = job.paths().map(Path::to_path_buf).collect::<Vec<_>>();
+        self.changed_since_snapshot.extend(reduced.iter().cloned());
+        // Interrupt other workers and update them so they actually take advantage of it
         tracing::trace!("Sending a kill message to all other workers");
-        let mut workers_to_restart = self.workers.drain((worker.0 + 1)..).collect::<Vec<_>>();
-        workers_to_restart.extend(self.workers.drain(..worker.0));
-        for w in &workers_to_restart {
+        let (kept, workers_to_update) = std::mem::take(&mut self.workers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(w, _)| *w == worker);
+        self.workers = kept;
+        // Idle workers are not running any job, so they can be updated right away
+        let idle_workers = std::mem::take(&mut self.idle_workers);
+        let (idle_workers, mut workers_to_update) = workers_to_update
+            .into_iter()
+            .partition::<Vec<_>, _>(|(w, _)| idle_workers.contains(w));
+        for (id, w) in idle_workers {
+            self.update_worker(id, w, &reduced, None)?;
+        }
+        for (_, w) in &workers_to_update {
             w.send_kill();
         }
         tracing::trace!("Waiting for all other workers to reply to the kill message");
-        while !workers_to_restart.is_empty() {
+        while !workers_to_update.is_empty() {
             let mut sel = crossbeam_channel::Select::new();
-            for w in workers_to_restart.iter() {
+            for (_, w) in workers_to_update.iter() {
                 sel.recv(w.get_receiver());
             }
             sel.recv(&self.kill_trigger);
             let oper = sel.select();
             let w = oper.index();
-            if w == workers_to_restart.len() {
+            if w == workers_to_update.len() {
                 let _ = oper.recv(&self.kill_trigger);
                 anyhow::bail!("Killed by user");
             }
-            let _ = oper.recv(workers_to_restart[w].get_receiver());
-            let worker = workers_to_restart.swap_remove(w);
-            self.spawn_worker(worker.recover_bar())
-                .context("restarting workers after one of them found a successful reduction")?;
+            let res = oper
+                .recv(workers_to_update[w].1.get_receiver())
+                .expect("Workers should never disconnect first");
+            let (id, worker) = workers_to_update.swap_remove(w);
+            self.update_worker(id, worker, &reduced, Some(res))?;
+        }
+        tracing::trace!("All workers replied to the kill message and were updated");
+        Ok(())
+    }
+
+    /// Bring a worker up to date after the `reduced` files were reduced, and send it a
+    /// new job
+    ///
+    /// `last_job` is the result of the job the worker was running when it got
+    /// interrupted, if any. Only the files that changed are copied over, unless the
+    /// worker's checkout could be in an unknown state, in which case it is replaced
+    /// by a fresh copy.
+    fn update_worker(
+        &mut self,
+        id: WorkerId,
+        worker: Worker,
+        reduced: &[PathBuf],
+        last_job: Option<JobResult>,
+    ) -> anyhow::Result<()> {
+        let mut changed = reduced.to_vec();
+        match last_job {
+            Some(JobResult {
+                job,
+                res: Ok(JobStatus::Reduced(desc)),
+            }) => {
+                tracing::debug!("Discarding reduction found while being interrupted: {desc}");
+                changed.extend(job.paths().map(Path::to_path_buf));
+            }
+            None | Some(JobResult { res: Ok(_), .. }) => (),
+            Some(JobResult { job, res: Err(e) }) => {
+                tracing::debug!("Worker failed while being interrupted, replacing it with a fresh copy\nJob: {job:?}\nError:\n---\n{e:?}\n---");
+                return self
+                    .spawn_worker(worker.recover_bar())
+                    .context("restarting workers after one of them found a successful reduction");
+            }
+        }
+        if let Err(e) = worker.sync_files(self.root.path(), &changed) {
+            tracing::warn!("Failed updating worker after a successful reduction, replacing it with a fresh copy\nError:\n---\n{e:?}\n---");
+            return self
+                .spawn_worker(worker.recover_bar())
+                .context("restarting workers after one of them found a successful reduction");
         }
-        tracing::trace!("All workers replied to the kill message and were restarted");
-        Ok(WorkerIdx(0)) // We removed all workers then respawned new ones
+        self.workers.push((id, worker));
+        self.send_job_to(id)
     }
 
-    fn snapshot(&self) -> anyhow::Result<()> {
+    /// Retrieve a worker's successful reduction to "current best" state
+    fn save_reduction(&self, workerdir: &Path) -> anyhow::Result<()> {
+        let my_workdir = self.root.path().join(WORKDIR);
+        std::fs::remove_dir_all(&my_workdir)
+            .with_context(|| format!("removing \"current status\" path {my_workdir:?}"))?;
+        std::fs::create_dir(&my_workdir)
+            .with_context(|| format!("recreating \"current status\" path {my_workdir:?}"))?;
+        // Only the workdir, as the rest of the worker's directory is its own business
+        copy_dir_contents(&workerdir.join(WORKDIR), &my_workdir).with_context(|| {
+            format!("copying successful reduction from {workerdir:?} to {my_workdir:?}")
+        })?;
+        Ok(())
+    }
+
+    fn snapshot(&mut self) -> anyhow::Result<()> {
         let now = Cal::new(Iso, Utc).now();
         let now = now.icu();
-        let snap_dir = self.snap_dir.join(format!(
+        let snap_name = format!(
             "{:04}-{:02}-{:02}-{:02}-{:02}-{:02}-{:03}",
             now.date.year().number,
             now.date.month().ordinal,
@@ -319,30 +1161,125 @@ impl<'a, T: Test> Runner<'a, T> {
             now.time.minute.number(),
             now.time.second.number(),
             now.time.nanosecond.number() / 1_000_000,
-        ));
+        );
+        let partial_dir = self
+            .snap_dir
+            .join(format!("{PARTIAL_SNAPSHOT_PREFIX}{snap_name}"));
+        let snap_dir = self.snap_dir.join(&snap_name);
         let workdir = self.root.path().join(WORKDIR);
-        std::fs::create_dir(&snap_dir)
-            .with_context(|| format!("creating snapshot directory {snap_dir:?}"))?;
-        copy_dir_contents(&workdir, &snap_dir)?;
+        std::fs::create_dir(&partial_dir)
+            .with_context(|| format!("creating snapshot directory {partial_dir:?}"))?;
+        // Regularly take a full snapshot, so that old layers can be removed
+        let base = self
+            .last_snapshot
+            .clone()
+            .filter(|(_, layers)| self.changed_only && layers + 1 < self.max_snaps);
+        match &base {
+            Some((base, _)) => {
+                write_layer(&workdir, &partial_dir, base, &self.changed_since_snapshot)?
+            }
+            None => copy_dir_contents(&workdir, &partial_dir)?,
+        }
+        if let Some(format_command) = &self.format_command {
+            self.format_snapshot(format_command, &partial_dir)?;
+        }
         self.test
-            .cleanup_snapshot(&snap_dir)
-            .with_context(|| format!("cleaning up snapshot {snap_dir:?}"))?;
+            .cleanup_snapshot(&partial_dir)
+            .with_context(|| format!("cleaning up snapshot {partial_dir:?}"))?;
+        std::fs::rename(&partial_dir, &snap_dir)
+            .with_context(|| format!("moving finished snapshot {partial_dir:?} to {snap_dir:?}"))?;
         tracing::info!("Wrote a reduced snapshot in {snap_dir:?}");
+        self.last_snapshot = Some((snap_name, base.map_or(0, |(_, layers)| layers + 1)));
+        self.changed_since_snapshot.clear();
+        (self.on_event)(&ReductionEvent::Snapshot {
+            path: snap_dir,
+            total_size: self.total_size(),
+        });
+        if let (Some(cache), Some(path)) = (&self.worker_config.test_cache, &self.test_cache_path) {
+            cache.save(path)?;
+        }
         self.remove_old_snapshots()?;
         tracing::trace!("Removed old snapshots from {:?}", self.snap_dir);
         Ok(())
     }
 
+    /// Format the files of the snapshot in `dir`, unless it makes them uninteresting
+    fn format_snapshot(&self, format_command: &Path, dir: &Path) -> anyhow::Result<()> {
+        let workdir = self.root.path().join(WORKDIR);
+        let mut changed = false;
+        for f in self.files.keys() {
+            let path = dir.join(f);
+            let before = std::fs::read(&path)
+                .with_context(|| format!("reading file {path:?} before formatting it"))?;
+            let status = std::process::Command::new(format_command)
+                .arg(&path)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .with_context(|| {
+                    format!("running format command {format_command:?} on {path:?}")
+                })?;
+            if !status.success() {
+                tracing::debug!("Format command failed on {path:?} ({status}), leaving it as-is");
+                std::fs::write(&path, &before)
+                    .with_context(|| format!("restoring file {path:?} after failed formatting"))?;
+                continue;
+            }
+            changed |= std::fs::read(&path)
+                .with_context(|| format!("reading formatted file {path:?}"))?
+                != before;
+        }
+        if !changed {
+            return Ok(());
+        }
+        self.worker_config.tests_run.fetch_add(1, Ordering::Relaxed);
+        match self.test.test_interesting(
+            dir,
+            &self.kill_trigger,
+            "Checking that the formatted snapshot is interesting",
+            0,
+        )? {
+            TestResult::Interesting => (),
+            TestResult::NotInteresting => {
+                tracing::info!(
+                    "Formatting made the snapshot not interesting, keeping it unformatted"
+                );
+                for f in self.files.keys() {
+                    let (from, to) = (workdir.join(f), dir.join(f));
+                    std::fs::copy(&from, &to).with_context(|| {
+                        format!("restoring unformatted file {from:?} to {to:?}")
+                    })?;
+                }
+            }
+            TestResult::Interrupted => anyhow::bail!("Killed by user"),
+        }
+        Ok(())
+    }
+
     fn remove_old_snapshots(&self) -> anyhow::Result<()> {
         let mut snapshots = std::fs::read_dir(&self.snap_dir)
             .with_context(|| format!("listing snapshots in {:?}", self.snap_dir))?
             .collect::<Result<Vec<_>, _>>()
             .with_context(|| format!("listing snapshots in {:?}", self.snap_dir))?;
+        snapshots.retain(|s| is_snapshot(&s.file_name()));
         if snapshots.len() <= self.max_snaps {
             return Ok(());
         }
         snapshots.sort_by_key(|s| s.file_name());
-        snapshots.truncate(snapshots.len() - self.max_snaps);
+        let kept = snapshots.split_off(snapshots.len() - self.max_snaps);
+        // Layers are useless without the snapshots they are based on
+        let mut bases = HashSet::new();
+        for s in kept {
+            let mut path = s.path();
+            while let Some(base) = layer_base(&path)? {
+                path = path.with_file_name(&base);
+                if !bases.insert(base) {
+                    break;
+                }
+            }
+        }
+        snapshots.retain(|s| !bases.contains(&*s.file_name().to_string_lossy()));
         tracing::trace!("Too many snapshots, removing {snapshots:?}");
         for s in snapshots {
             let path = s.path();
@@ -1,8 +1,16 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+};
 
 use anyhow::Context;
 use crossbeam_channel::RecvTimeoutError;
 
+use crate::util::clone_tempdir;
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum TestResult {
     Interesting,
@@ -40,13 +48,87 @@ pub trait Test: 'static + Send + Sync {
     /// This will be called after each snapshot, on the folder that the user will then
     /// read.
     fn cleanup_snapshot(&self, root: &Path) -> anyhow::Result<()>;
+
+    /// Whether edits to `path` could change whether the input is interesting
+    ///
+    /// This is an optional optimization hint. Returning `true` (the default) means
+    /// that the test must be run after editing `path`, which is always correct.
+    ///
+    /// Returning `false` means that edits to `path` provably cannot change whether
+    /// the input is interesting. In that case, the test will not be run at all for
+    /// edits to `path`, and the edits will be assumed to be interesting.
+    fn affects_interestingness(&self, _path: &Path) -> bool {
+        true
+    }
+
+    /// Quickly check whether the input could possibly be interesting
+    ///
+    /// This is run before each `test_interesting`, with `edited` being the path,
+    /// relative to `root`, of the file that was just edited. If it returns
+    /// `Ok(false)`, then the input is considered not interesting, without running
+    /// `test_interesting` at all.
+    ///
+    /// This should be much faster than `test_interesting`, for instance checking
+    /// that the edited file still parses. By default, it accepts everything.
+    fn smoke_test(&self, _root: &Path, _edited: &Path) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
+    /// Identity of this test, used to decide whether cached results can be reused
+    ///
+    /// Test results saved by a previous run are only reused if this returns the
+    /// same value as during that run. Returning `None` (the default) means that
+    /// results are never saved across runs.
+    fn identity(&self) -> Option<u64> {
+        None
+    }
+
+    /// Human-readable description of the test, like the command it runs
+    ///
+    /// This is only used in the report written by `--report-file`, so that the
+    /// reduction can be reproduced. Returning `None` (the default) omits it.
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether this test only works when reducing a standalone file
+    ///
+    /// If this returns `true`, the reducer refuses to run on a directory. The root
+    /// passed to the other methods then always contains only the file being reduced.
+    fn requires_single_file(&self) -> bool {
+        false
+    }
 }
 
-pub struct ShellTest<PrepFn, CleanFn, SnapCleanFn> {
+/// Test used for edits that cannot change interestingness, as per `Test::affects_interestingness`
+pub(crate) struct AssumeInteresting;
+
+impl Test for AssumeInteresting {
+    fn test_interesting(
+        &self,
+        _root: &Path,
+        _kill_trigger: &crossbeam_channel::Receiver<()>,
+        _attempt_name: &str,
+        _attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        Ok(TestResult::Interesting)
+    }
+
+    fn cleanup_snapshot(&self, _root: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct ShellTest<PrepFn, CleanFn, SnapCleanFn, SmokeFn> {
     prep: PrepFn,
     test: PathBuf,
+    workdir: PathBuf,
+    debug_output: Option<PathBuf>,
+    hermetic: bool,
+    tmp_dir: Option<PathBuf>,
     clean: CleanFn,
     snap_clean: SnapCleanFn,
+    smoke: SmokeFn,
 }
 
 impl
@@ -54,75 +136,187 @@ impl
         fn(&Path) -> anyhow::Result<()>,
         fn(&Path) -> anyhow::Result<()>,
         fn(&Path) -> anyhow::Result<()>,
+        fn(&Path, &Path) -> anyhow::Result<bool>,
     >
 {
     pub fn new(test: PathBuf) -> Self {
         fn noop(_: &Path) -> anyhow::Result<()> {
             Ok(())
         }
+        fn accept_all(_: &Path, _: &Path) -> anyhow::Result<bool> {
+            Ok(true)
+        }
         Self {
             prep: noop,
             test,
+            workdir: PathBuf::new(),
+            debug_output: None,
+            hermetic: false,
+            tmp_dir: None,
             clean: noop,
             snap_clean: noop,
+            smoke: accept_all,
         }
     }
 }
 
-impl<PrepFn, CleanFn, SnapCleanFn> ShellTest<PrepFn, CleanFn, SnapCleanFn> {
+impl<PrepFn, CleanFn, SnapCleanFn, SmokeFn> ShellTest<PrepFn, CleanFn, SnapCleanFn, SmokeFn> {
     pub fn with_fixture<NewPrep, NewClean>(
         self,
         prep: NewPrep,
         clean: NewClean,
-    ) -> ShellTest<NewPrep, NewClean, SnapCleanFn> {
+    ) -> ShellTest<NewPrep, NewClean, SnapCleanFn, SmokeFn> {
         ShellTest {
             prep,
             test: self.test,
+            workdir: self.workdir,
+            debug_output: self.debug_output,
+            hermetic: self.hermetic,
+            tmp_dir: self.tmp_dir,
             clean,
             snap_clean: self.snap_clean,
+            smoke: self.smoke,
         }
     }
 
     pub fn with_snapshot_cleanup<NewSnap>(
         self,
         snap_clean: NewSnap,
-    ) -> ShellTest<PrepFn, CleanFn, NewSnap> {
+    ) -> ShellTest<PrepFn, CleanFn, NewSnap, SmokeFn> {
         ShellTest {
             prep: self.prep,
             test: self.test,
+            workdir: self.workdir,
+            debug_output: self.debug_output,
+            hermetic: self.hermetic,
+            tmp_dir: self.tmp_dir,
             clean: self.clean,
             snap_clean,
+            smoke: self.smoke,
         }
     }
+
+    /// Run `smoke` before each test, see `Test::smoke_test` for the details
+    pub fn with_smoke_test<NewSmoke>(
+        self,
+        smoke: NewSmoke,
+    ) -> ShellTest<PrepFn, CleanFn, SnapCleanFn, NewSmoke> {
+        ShellTest {
+            prep: self.prep,
+            test: self.test,
+            workdir: self.workdir,
+            debug_output: self.debug_output,
+            hermetic: self.hermetic,
+            tmp_dir: self.tmp_dir,
+            clean: self.clean,
+            snap_clean: self.snap_clean,
+            smoke,
+        }
+    }
+
+    /// Run the test in subdirectory `workdir` of the directory being reduced
+    ///
+    /// `workdir` must be a relative path that stays within the directory being
+    /// reduced. The fixture and snapshot cleanup functions are still called on the
+    /// root of the directory being reduced.
+    pub fn with_workdir(mut self, workdir: PathBuf) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            workdir
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir)),
+            "Test working directory {workdir:?} must be a relative path staying within the root",
+        );
+        self.workdir = workdir;
+        Ok(self)
+    }
+
+    /// Save the output of the most recent test run to `path`
+    ///
+    /// The test's stdout and stderr are captured, and after each test that was not
+    /// interrupted, `path` is overwritten with the output of this test. This is
+    /// intended for figuring out why the reduction does not progress as expected.
+    pub fn with_debug_output(mut self, path: PathBuf) -> Self {
+        self.debug_output = Some(path);
+        self
+    }
+
+    /// Run each test in a fresh copy of the directory being reduced
+    ///
+    /// The copy is removed after the test. This is slower, as it loses the benefits
+    /// of incremental compilation, but avoids flaky results with tests that are
+    /// affected by the files left over by previous runs. The copies are made in
+    /// `tmp_dir`, or in the system's temporary directory if it is `None`.
+    pub fn with_hermetic(mut self, tmp_dir: Option<PathBuf>) -> Self {
+        self.hermetic = true;
+        self.tmp_dir = tmp_dir;
+        self
+    }
 }
 
-impl<PrepFn, CleanFn, SnapCleanFn> Test for ShellTest<PrepFn, CleanFn, SnapCleanFn>
+impl<PrepFn, CleanFn, SnapCleanFn, SmokeFn> Test
+    for ShellTest<PrepFn, CleanFn, SnapCleanFn, SmokeFn>
 where
     PrepFn: 'static + Send + Sync + Fn(&Path) -> anyhow::Result<()>,
     CleanFn: 'static + Send + Sync + Fn(&Path) -> anyhow::Result<()>,
     SnapCleanFn: 'static + Send + Sync + Fn(&Path) -> anyhow::Result<()>,
+    SmokeFn: 'static + Send + Sync + Fn(&Path, &Path) -> anyhow::Result<bool>,
 {
     fn test_interesting(
         &self,
         root: &Path,
         kill_trigger: &crossbeam_channel::Receiver<()>,
-        _attempt_name: &str,
+        attempt_name: &str,
         _attempt_id: u64,
     ) -> anyhow::Result<TestResult> {
+        let copy = match self.hermetic {
+            true => Some(clone_tempdir(root, None, self.tmp_dir.as_deref())?),
+            false => None,
+        };
+        let root = copy.as_ref().map_or(root, |c| c.path());
         (self.prep)(root)?;
-        let mut child = std::process::Command::new(&self.test)
-            .current_dir(root)
-            .spawn()
-            .with_context(|| {
-                format!("spawning test command {:?} in workdir {root:?}", self.test)
-            })?;
+        let workdir = root.join(&self.workdir);
+        let mut command = std::process::Command::new(&self.test);
+        command.current_dir(&workdir);
+        // Output goes to a file rather than a pipe, so that the child never blocks
+        // on a full pipe while we are polling for it to exit
+        let mut output = match &self.debug_output {
+            None => None,
+            Some(path) => {
+                let dir = match path.parent() {
+                    Some(dir) if dir != Path::new("") => dir,
+                    _ => Path::new("."),
+                };
+                let mut file = tempfile::NamedTempFile::new_in(dir).with_context(|| {
+                    format!("creating temporary file for test output in {dir:?}")
+                })?;
+                writeln!(file, "# Attempt: {attempt_name}")
+                    .context("writing header of test output")?;
+                let stdout = file.as_file().try_clone().context("cloning output file")?;
+                let stderr = file.as_file().try_clone().context("cloning output file")?;
+                command
+                    .stdout(Stdio::from(stdout))
+                    .stderr(Stdio::from(stderr));
+                Some((file, path))
+            }
+        };
+        let mut child = command.spawn().with_context(|| {
+            format!(
+                "spawning test command {:?} in workdir {workdir:?}",
+                self.test
+            )
+        })?;
         let res = 'res: loop {
             match child.try_wait() {
                 Ok(Some(exit)) => {
+                    if let Some((file, path)) = output.take() {
+                        if let Err(e) = save_debug_output(file, path, exit) {
+                            break 'res Err(e);
+                        }
+                    }
                     break 'res Ok(match exit.success() {
                         true => TestResult::Interesting,
                         false => TestResult::NotInteresting,
-                    })
+                    });
                 }
                 Err(e) => break 'res Err(e).context("waiting for child command"),
                 Ok(None) => (),
@@ -147,4 +341,313 @@ where
     fn cleanup_snapshot(&self, root: &Path) -> anyhow::Result<()> {
         (self.snap_clean)(root)
     }
+
+    fn smoke_test(&self, root: &Path, edited: &Path) -> anyhow::Result<bool> {
+        (self.smoke)(root, edited)
+    }
+
+    /// Hash of the test's path, working directory and contents
+    fn identity(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        self.test.hash(&mut hasher);
+        self.workdir.hash(&mut hasher);
+        std::fs::read(&self.test).ok().hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    /// The test command, run from the test's working directory
+    fn description(&self) -> Option<String> {
+        Some(match self.workdir == Path::new("") {
+            true => format!("{}", self.test.display()),
+            false => format!("cd {} && {}", self.workdir.display(), self.test.display()),
+        })
+    }
+}
+
+/// Test that delegates to a long-lived server listening on a Unix socket
+///
+/// This avoids paying the startup cost of the checker on each test, which is useful
+/// for instance with language servers. For each test, a new connection is opened to
+/// the socket, and the path to the directory to test is sent over it, followed by a
+/// `\n`. The server must then reply with either `interesting\n` or
+/// `not interesting\n`. If the test gets interrupted, the connection is closed
+/// without waiting for the reply.
+#[cfg(unix)]
+pub struct SocketTest<SnapCleanFn> {
+    socket: PathBuf,
+    snap_clean: SnapCleanFn,
+}
+
+#[cfg(unix)]
+impl SocketTest<fn(&Path) -> anyhow::Result<()>> {
+    pub fn new(socket: PathBuf) -> Self {
+        fn noop(_: &Path) -> anyhow::Result<()> {
+            Ok(())
+        }
+        Self {
+            socket,
+            snap_clean: noop,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<SnapCleanFn> SocketTest<SnapCleanFn> {
+    pub fn with_snapshot_cleanup<NewSnap>(self, snap_clean: NewSnap) -> SocketTest<NewSnap> {
+        SocketTest {
+            socket: self.socket,
+            snap_clean,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<SnapCleanFn> Test for SocketTest<SnapCleanFn>
+where
+    SnapCleanFn: 'static + Send + Sync + Fn(&Path) -> anyhow::Result<()>,
+{
+    fn test_interesting(
+        &self,
+        root: &Path,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+        _attempt_name: &str,
+        _attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        use std::{
+            io::{BufRead, ErrorKind},
+            os::unix::{ffi::OsStrExt, net::UnixStream},
+        };
+
+        let socket = &self.socket;
+        let mut stream = UnixStream::connect(socket)
+            .with_context(|| format!("connecting to test socket {socket:?}"))?;
+        stream
+            .write_all(&[root.as_os_str().as_bytes(), b"\n"].concat())
+            .with_context(|| format!("sending request to test socket {socket:?}"))?;
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+            .context("setting the test socket timeout")?;
+        let mut reader = std::io::BufReader::new(stream);
+        let mut reply = Vec::new();
+        loop {
+            match reader.read_until(b'\n', &mut reply) {
+                Ok(_) if reply.ends_with(b"\n") => break,
+                Ok(_) => anyhow::bail!(
+                    "test socket {socket:?} closed the connection before replying, after sending {:?}",
+                    String::from_utf8_lossy(&reply),
+                ),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("reading reply from test socket {socket:?}"))
+                }
+            }
+            match kill_trigger.try_recv() {
+                Ok(()) => return Ok(TestResult::Interrupted),
+                Err(crossbeam_channel::TryRecvError::Empty) => (),
+                Err(e @ crossbeam_channel::TryRecvError::Disconnected) => {
+                    return Err(e).context("waiting for kill trigger")
+                }
+            }
+        }
+        match &reply[..] {
+            b"interesting\n" => Ok(TestResult::Interesting),
+            b"not interesting\n" => Ok(TestResult::NotInteresting),
+            _ => anyhow::bail!(
+                "test socket {socket:?} sent unexpected reply {:?}",
+                String::from_utf8_lossy(&reply),
+            ),
+        }
+    }
+
+    fn cleanup_snapshot(&self, root: &Path) -> anyhow::Result<()> {
+        (self.snap_clean)(root)
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("Server listening on {}", self.socket.display()))
+    }
+}
+
+/// Test that pipes the file being reduced to a command, for standalone files
+///
+/// The command is spawned for each test, with the contents of the file on its
+/// stdin, and must print either `interesting\n` or `not interesting\n` on its
+/// stdout. It is run in the current directory of the reducer, rather than in the
+/// directory being reduced, so it never needs to read the file from disk. This
+/// makes it much faster than `ShellTest` for checkers that answer quickly.
+pub struct StdinTest {
+    command: PathBuf,
+}
+
+impl StdinTest {
+    pub fn new(command: PathBuf) -> Self {
+        Self { command }
+    }
+}
+
+impl Test for StdinTest {
+    fn test_interesting(
+        &self,
+        root: &Path,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+        _attempt_name: &str,
+        _attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        let path = single_file_in(root)?;
+        let contents =
+            std::fs::read(&path).with_context(|| format!("reading file to test {path:?}"))?;
+        let command = &self.command;
+        let mut child = std::process::Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("spawning test command {command:?}"))?;
+        let (mut stdin, mut stdout) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+        let (send_reply, reply) = crossbeam_channel::bounded(1);
+        let contents = &contents;
+        std::thread::scope(|s| {
+            // Moving `stdin` in closes it once written, for the command to see its end
+            let writer = s.spawn(move || match stdin.write_all(contents) {
+                // The command may reply without reading all of its input
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(()),
+            });
+            s.spawn(move || {
+                let mut reply = Vec::new();
+                let res = std::io::Read::read_to_end(&mut stdout, &mut reply).map(|_| reply);
+                // The receiver is gone if the test got interrupted
+                let _ = send_reply.send(res);
+            });
+            crossbeam_channel::select! {
+                recv(kill_trigger) -> msg => {
+                    msg.context("waiting for kill trigger")?;
+                    child.kill().context("killing child")?;
+                    child.wait().context("waiting for killed child")?;
+                    Ok(TestResult::Interrupted)
+                }
+                recv(reply) -> reply => {
+                    let reply = reply
+                        .expect("The reply is always sent")
+                        .with_context(|| format!("reading reply of test command {command:?}"))?;
+                    let exit = child.wait().context("waiting for child command")?;
+                    writer
+                        .join()
+                        .expect("Writing to the test command panicked")
+                        .with_context(|| format!("writing to test command {command:?}"))?;
+                    match &reply[..] {
+                        b"interesting\n" => Ok(TestResult::Interesting),
+                        b"not interesting\n" => Ok(TestResult::NotInteresting),
+                        _ => anyhow::bail!(
+                            "test command {command:?} exited with {exit} after printing unexpected reply {:?}",
+                            String::from_utf8_lossy(&reply),
+                        ),
+                    }
+                }
+            }
+        })
+    }
+
+    fn cleanup_snapshot(&self, _root: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Hash of the command's path and contents
+    fn identity(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        self.command.hash(&mut hasher);
+        std::fs::read(&self.command).ok().hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{} < file", self.command.display()))
+    }
+
+    fn requires_single_file(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the path to the only file in `root`, as is the case for standalone files
+fn single_file_in(root: &Path) -> anyhow::Result<PathBuf> {
+    let mut files = std::fs::read_dir(root)
+        .with_context(|| format!("listing directory {root:?}"))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("listing directory {root:?}"))?;
+    anyhow::ensure!(
+        files.len() == 1,
+        "expected a single file in {root:?}, found {files:?}"
+    );
+    Ok(files.pop().unwrap())
+}
+
+/// Test that calls a Rust closure
+///
+/// The closure is called with the directory to test and the kill trigger, and has
+/// the same contract as `Test::test_interesting`. By default, snapshots are not
+/// cleaned up.
+pub struct FnTest<F, SnapCleanFn> {
+    test: F,
+    snap_clean: SnapCleanFn,
+}
+
+impl<F> FnTest<F, fn(&Path) -> anyhow::Result<()>>
+where
+    F: Fn(&Path, &crossbeam_channel::Receiver<()>) -> anyhow::Result<TestResult>,
+{
+    pub fn new(test: F) -> Self {
+        fn noop(_: &Path) -> anyhow::Result<()> {
+            Ok(())
+        }
+        Self {
+            test,
+            snap_clean: noop,
+        }
+    }
+}
+
+impl<F, SnapCleanFn> FnTest<F, SnapCleanFn> {
+    pub fn with_snapshot_cleanup<NewSnap>(self, snap_clean: NewSnap) -> FnTest<F, NewSnap> {
+        FnTest {
+            test: self.test,
+            snap_clean,
+        }
+    }
+}
+
+impl<F, SnapCleanFn> Test for FnTest<F, SnapCleanFn>
+where
+    F: 'static
+        + Send
+        + Sync
+        + Fn(&Path, &crossbeam_channel::Receiver<()>) -> anyhow::Result<TestResult>,
+    SnapCleanFn: 'static + Send + Sync + Fn(&Path) -> anyhow::Result<()>,
+{
+    fn test_interesting(
+        &self,
+        root: &Path,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+        _attempt_name: &str,
+        _attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        (self.test)(root, kill_trigger)
+    }
+
+    fn cleanup_snapshot(&self, root: &Path) -> anyhow::Result<()> {
+        (self.snap_clean)(root)
+    }
+}
+
+fn save_debug_output(
+    mut file: tempfile::NamedTempFile,
+    path: &Path,
+    exit: std::process::ExitStatus,
+) -> anyhow::Result<()> {
+    writeln!(file, "# Test exited with {exit}").context("writing footer of test output")?;
+    file.persist(path)
+        .with_context(|| format!("saving test output to {path:?}"))?;
+    Ok(())
 }
@@ -1,10 +1,34 @@
-use std::{path::Path, time::Duration};
+use std::{ffi::OsStr, path::Path, time::Duration};
 
 use anyhow::Context;
 use tempfile::TempDir;
 
+use crate::ProgressStyle;
+
 pub(crate) const WORKDIR: &str = "workdir";
 pub(crate) const TMPDIR: &str = "tmpdir";
+/// Repository used by workers with `--git-backup`, kept out of the tree being reduced
+pub(crate) const GITDIR: &str = "git";
+
+/// Prefix of the snapshots that are still being written
+///
+/// Snapshots are first written under a name with this prefix, then renamed to their
+/// actual name, so that a snapshot with its actual name is always complete.
+pub(crate) const PARTIAL_SNAPSHOT_PREFIX: &str = ".partial-";
+
+/// File of the snapshot directory recording the standalone file being reduced
+///
+/// Snapshots only contain the wrapped file, so this is what allows `--resume` to
+/// write the result back to the original file.
+pub(crate) const SINGLE_FILE_RECORD: &str = ".single-file";
+
+/// Whether the entry of the snapshot directory named `name` is a finished snapshot
+///
+/// All the other entries, like partial snapshots or the test cache, have names
+/// starting with a `.`.
+pub(crate) fn is_snapshot(name: &OsStr) -> bool {
+    !name.to_string_lossy().starts_with('.')
+}
 
 pub(crate) fn copy_dir_contents(from: &Path, to: &Path) -> anyhow::Result<()> {
     fs_extra::dir::copy(
@@ -16,20 +40,66 @@ pub(crate) fn copy_dir_contents(from: &Path, to: &Path) -> anyhow::Result<()> {
     .map(|_| ())
 }
 
-pub(crate) fn clone_tempdir(root: &Path) -> anyhow::Result<TempDir> {
-    let dir = tempfile::Builder::new()
-        .prefix("tree-sitter-reduce-worker-")
-        .tempdir()
-        .context("creating temporary directory")?;
+/// Remove the file at `path` in `dir` if it exists, and its parents that end up empty
+pub(crate) fn remove_file_and_empty_parents(dir: &Path, path: &Path) -> anyhow::Result<()> {
+    let filepath = dir.join(path);
+    match std::fs::remove_file(&filepath) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e).with_context(|| format!("removing file {filepath:?}")),
+    }
+    for parent in path.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() {
+            break;
+        }
+        // This fails if the directory is not empty, which is where to stop
+        match std::fs::remove_dir(dir.join(parent)) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Create a temporary directory, in `tmp_dir` if set or the system default otherwise
+///
+/// The directory is named `prefix` followed by random characters, or exactly
+/// `stable_name` if it is set.
+pub(crate) fn make_tempdir(
+    prefix: &str,
+    stable_name: Option<&str>,
+    tmp_dir: Option<&Path>,
+) -> anyhow::Result<TempDir> {
+    let mut builder = tempfile::Builder::new();
+    match stable_name {
+        Some(name) => builder.prefix(name).rand_bytes(0),
+        None => builder.prefix(prefix),
+    };
+    match tmp_dir {
+        Some(tmp_dir) => builder
+            .tempdir_in(tmp_dir)
+            .with_context(|| format!("creating temporary directory in {tmp_dir:?}")),
+        None => builder.tempdir().context("creating temporary directory"),
+    }
+}
+
+pub(crate) fn clone_tempdir(
+    root: &Path,
+    stable_name: Option<&str>,
+    tmp_dir: Option<&Path>,
+) -> anyhow::Result<TempDir> {
+    let dir = make_tempdir("tree-sitter-reduce-worker-", stable_name, tmp_dir)?;
     copy_dir_contents(root, dir.path())?;
     Ok(dir)
 }
 
-pub(crate) fn copy_to_tempdir(root: &Path) -> anyhow::Result<TempDir> {
-    let dir = tempfile::Builder::new()
-        .prefix("tree-sitter-reduce-runner-")
-        .tempdir()
-        .context("creating temporary directory")?;
+pub(crate) fn copy_to_tempdir(
+    root: &Path,
+    stable_name: Option<&str>,
+    tmp_dir: Option<&Path>,
+) -> anyhow::Result<TempDir> {
+    let dir = make_tempdir("tree-sitter-reduce-runner-", stable_name, tmp_dir)?;
     let actual_path = dir.path().join(WORKDIR);
     std::fs::create_dir(&actual_path)
         .context("creating workdir nested under the temporary directory")?;
@@ -39,19 +109,76 @@ pub(crate) fn copy_to_tempdir(root: &Path) -> anyhow::Result<TempDir> {
     Ok(dir)
 }
 
-pub(crate) fn init_env(no_progress_bars: bool) -> anyhow::Result<indicatif::MultiProgress> {
+/// Create a temporary directory containing only a copy of file `path`
+///
+/// This makes it possible to reduce standalone files, as the rest of the reducer
+/// expects the root to be a directory.
+pub(crate) fn wrap_single_file(path: &Path, tmp_dir: Option<&Path>) -> anyhow::Result<TempDir> {
+    let dir = make_tempdir("tree-sitter-reduce-file-", None, tmp_dir)?;
+    let name = path
+        .file_name()
+        .with_context(|| format!("getting the file name of {path:?}"))?;
+    let dest = dir.path().join(name);
+    std::fs::copy(path, &dest).with_context(|| format!("copying file {path:?} to {dest:?}"))?;
+    Ok(dir)
+}
+
+/// Name of the runner directory in the tempdir root
+pub(crate) const RUNNER_TEMPDIR: &str = "runner";
+
+/// Name of the directory of the worker with id `id` in the tempdir root
+pub(crate) fn worker_tempdir(id: u64) -> String {
+    format!("worker-{id}")
+}
+
+/// Prepare the tempdir root for a new run
+///
+/// This creates it if needed, and removes the runner and worker directories left
+/// over by a previous run. Other files are left alone, in case the user pointed
+/// the tempdir root to a directory that also contains other things.
+pub(crate) fn clean_tempdir_root(path: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path).with_context(|| format!("creating tempdir root {path:?}"))?;
+    for entry in
+        std::fs::read_dir(path).with_context(|| format!("listing tempdir root {path:?}"))?
+    {
+        let entry = entry.with_context(|| format!("listing tempdir root {path:?}"))?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let is_worker = name
+            .strip_prefix("worker-")
+            .map_or(false, |id| id.parse::<u64>().is_ok());
+        if name == RUNNER_TEMPDIR || is_worker {
+            let entry = entry.path();
+            tracing::debug!("Removing leftover temporary directory {entry:?}");
+            std::fs::remove_dir_all(&entry)
+                .with_context(|| format!("removing leftover temporary directory {entry:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn init_env(
+    progress_style: ProgressStyle,
+    log_level: Option<&str>,
+) -> anyhow::Result<indicatif::MultiProgress> {
     // Setup the progress bar
     let progress = indicatif::MultiProgress::new();
     progress.set_move_cursor(true);
 
     // Setup tracing
+    let filter = match log_level {
+        Some(l) => tracing_subscriber::EnvFilter::try_new(l)
+            .with_context(|| format!("parsing log level {l:?}"))?,
+        None => tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+    };
     let format = tracing_subscriber::fmt::format().with_target(false);
     let subscriber = tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_env_filter(filter)
         .event_format(format);
 
     // Enable (or not) drawing the bars
-    if no_progress_bars {
+    if progress_style == ProgressStyle::None {
         subscriber.init();
         progress.set_draw_target(indicatif::ProgressDrawTarget::hidden());
     } else {
@@ -66,17 +193,22 @@ pub(crate) fn init_env(no_progress_bars: bool) -> anyhow::Result<indicatif::Mult
     Ok(progress)
 }
 
-pub(crate) fn make_progress_bar() -> indicatif::ProgressBar {
-    let progress_style = indicatif::ProgressStyle::with_template(
-        "{prefix:.grey.dim} {spinner:.blue.bold} {wide_msg:.bold}",
-    )
-    .expect("Failed to build progress bar style")
-    .tick_strings(&[
-        "⢀⠀", "⡀⠀", "⠄⠀", "⢂⠀", "⡂⠀", "⠅⠀", "⢃⠀", "⡃⠀", "⠍⠀", "⢋⠀", "⡋⠀", "⠍⠁", "⢋⠁", "⡋⠁", "⠍⠉",
-        "⠋⠉", "⠋⠉", "⠉⠙", "⠉⠙", "⠉⠩", "⠈⢙", "⠈⡙", "⢈⠩", "⡀⢙", "⠄⡙", "⢂⠩", "⡂⢘", "⠅⡘", "⢃⠨", "⡃⢐",
-        "⠍⡐", "⢋⠠", "⡋⢀", "⠍⡁", "⢋⠁", "⡋⠁", "⠍⠉", "⠋⠉", "⠋⠉", "⠉⠙", "⠉⠙", "⠉⠩", "⠈⢙", "⠈⡙", "⠈⠩",
-        "⠀⢙", "⠀⡙", "⠀⠩", "⠀⢘", "⠀⡘", "⠀⠨", "⠀⢐", "⠀⡐", "⠀⠠", "⠀⢀", "⠀⡀",
-    ]);
+pub(crate) fn make_progress_bar(style: ProgressStyle) -> indicatif::ProgressBar {
+    let template = match style {
+        ProgressStyle::Plain => "{prefix} {spinner} {wide_msg}",
+        _ => "{prefix:.grey.dim} {spinner:.blue.bold} {wide_msg:.bold}",
+    };
+    let progress_style = indicatif::ProgressStyle::with_template(template)
+        .expect("Failed to build progress bar style");
+    let progress_style = match style {
+        ProgressStyle::Plain => progress_style.tick_strings(&["-", "\\", "|", "/", " "]),
+        _ => progress_style.tick_strings(&[
+            "⢀⠀", "⡀⠀", "⠄⠀", "⢂⠀", "⡂⠀", "⠅⠀", "⢃⠀", "⡃⠀", "⠍⠀", "⢋⠀", "⡋⠀", "⠍⠁", "⢋⠁", "⡋⠁",
+            "⠍⠉", "⠋⠉", "⠋⠉", "⠉⠙", "⠉⠙", "⠉⠩", "⠈⢙", "⠈⡙", "⢈⠩", "⡀⢙", "⠄⡙", "⢂⠩", "⡂⢘", "⠅⡘",
+            "⢃⠨", "⡃⢐", "⠍⡐", "⢋⠠", "⡋⢀", "⠍⡁", "⢋⠁", "⡋⠁", "⠍⠉", "⠋⠉", "⠋⠉", "⠉⠙", "⠉⠙", "⠉⠩",
+            "⠈⢙", "⠈⡙", "⠈⠩", "⠀⢙", "⠀⡙", "⠀⠩", "⠀⢘", "⠀⡘", "⠀⠨", "⠀⢐", "⠀⡐", "⠀⠠", "⠀⢀", "⠀⡀",
+        ]),
+    };
     let bar = indicatif::ProgressBar::new_spinner();
     bar.set_style(progress_style);
     bar.set_prefix("#0000");
@@ -1,50 +1,106 @@
 use std::{
+    ffi::OsString,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 use anyhow::Context;
+use crossbeam_channel::RecvTimeoutError;
 use indicatif::ProgressBar;
 use tempfile::TempDir;
 
 use crate::{
+    cache::TestCache,
     job::{Job, JobResult, JobStatus},
-    util::{clone_tempdir, TMPDIR, WORKDIR},
+    test::AssumeInteresting,
+    util::{clone_tempdir, remove_file_and_empty_parents, worker_tempdir, GITDIR, TMPDIR, WORKDIR},
     Test, TestResult,
 };
 
+/// Delay before retrying a test that returned an error, see `WorkerConfig::test_retries`
+const TEST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Configuration shared by all the workers
+#[derive(Clone, Debug)]
+pub(crate) struct WorkerConfig {
+    /// Re-run the test after each successful reduction, and discard it if it is
+    /// not interesting the second time
+    pub(crate) double_check: bool,
+
+    /// Directory in which to put the worker copies of the directory being reduced
+    pub(crate) tmp_dir: Option<PathBuf>,
+
+    /// Directory in which to put the copies under stable names, instead of `tmp_dir`
+    pub(crate) tempdir_root: Option<PathBuf>,
+
+    /// Results of the test on already-tested states, if caching is enabled
+    pub(crate) test_cache: Option<Arc<TestCache>>,
+
+    /// Back up and restore the files with git, instead of copying them
+    pub(crate) git_backup: bool,
+
+    /// Number of times the test was actually run, not counting cached results
+    pub(crate) tests_run: Arc<AtomicU64>,
+
+    /// Number of times to run the test on each attempt, keeping the majority result
+    pub(crate) votes: usize,
+
+    /// Number of times to retry the test when it returns an error
+    pub(crate) test_retries: usize,
+}
+
 pub(crate) struct Worker {
     rootdir: TempDir,
     sender: crossbeam_channel::Sender<Job>,
     receiver: crossbeam_channel::Receiver<JobResult>,
     killer: crossbeam_channel::Sender<()>,
+    /// Receiving end of `killer`, to discard kill messages sent after the job ended
+    kill_trigger: crossbeam_channel::Receiver<()>,
     job_running: Arc<AtomicBool>,
     progress: ProgressBar,
 }
 
 struct WorkerThread<T> {
     rootdir: PathBuf,
-    test: T,
+    test: Arc<T>,
+    progress: ProgressBar,
     receiver: crossbeam_channel::Receiver<Job>,
     sender: crossbeam_channel::Sender<JobResult>,
     kill_trigger: crossbeam_channel::Receiver<()>,
     job_running: Arc<AtomicBool>,
+    config: WorkerConfig,
 }
 
 impl Worker {
     pub(crate) fn new(
+        id: u64,
         root: &Path,
         test: Arc<impl Test>,
         progress: ProgressBar,
+        mut config: WorkerConfig,
     ) -> anyhow::Result<Self> {
         // Tick the progress bar every 100ms
         progress.enable_steady_tick(std::time::Duration::from_millis(100));
 
         // First, copy the target into a directory
-        let rootdir = clone_tempdir(root)?;
+        let rootdir = match &config.tempdir_root {
+            Some(tempdir_root) => {
+                clone_tempdir(root, Some(&worker_tempdir(id)), Some(tempdir_root))?
+            }
+            None => clone_tempdir(root, None, config.tmp_dir.as_deref())?,
+        };
+        if config.git_backup {
+            if let Err(e) = git(rootdir.path(), &["init", "-q"], &[]) {
+                tracing::warn!(
+                    "Failed setting up git backups, falling back to copying files: {e:#}"
+                );
+                config.git_backup = false;
+            }
+        }
 
         // Then, prepare the communications channels
         let (sender, worker_receiver) = crossbeam_channel::bounded(1);
@@ -58,14 +114,17 @@ impl Worker {
             let progress = progress.clone();
             let rootdir = rootdir.path().to_path_buf();
             let test = test.clone();
+            let kill_trigger = kill_trigger.clone();
             move || {
                 WorkerThread::new(
                     rootdir,
-                    ReportingTest(test, progress),
+                    test,
+                    progress,
                     worker_receiver,
                     worker_sender,
                     kill_trigger,
                     job_running,
+                    config,
                 )
                 .run()
             }
@@ -75,6 +134,7 @@ impl Worker {
             receiver,
             sender,
             killer,
+            kill_trigger,
             job_running,
             progress,
         })
@@ -114,24 +174,64 @@ impl Worker {
     pub(crate) fn rootdir(&self) -> &Path {
         self.rootdir.path()
     }
+
+    /// Copy the files at `paths` from the working directory in `root` to this worker's
+    ///
+    /// This updates the worker after these files were reduced, without copying the
+    /// whole directory again, so that it can be reused for the next jobs. The files
+    /// that no longer exist in `root` are removed, along with the directories they
+    /// leave empty.
+    pub(crate) fn sync_files(&self, root: &Path, paths: &[PathBuf]) -> anyhow::Result<()> {
+        assert!(
+            !self.job_running.load(Ordering::Relaxed),
+            "Tried to update the files of a worker that still has a job running"
+        );
+        // The kill message could have arrived after the job was already done, in
+        // which case it must not interrupt the next job
+        while self.kill_trigger.try_recv().is_ok() {}
+        let workdir = self.rootdir().join(WORKDIR);
+        for path in paths {
+            let from = root.join(WORKDIR).join(path);
+            let to = workdir.join(path);
+            if !from.exists() {
+                remove_file_and_empty_parents(&workdir, path)?;
+                continue;
+            }
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("recursively creating directory {parent:?}"))?;
+            }
+            std::fs::copy(&from, &to)
+                .with_context(|| format!("copying reduced file {from:?} to {to:?}"))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn progress(&self) -> &ProgressBar {
+        &self.progress
+    }
 }
 
 impl<T: Test> WorkerThread<T> {
     fn new(
         rootdir: PathBuf,
-        test: T,
+        test: Arc<T>,
+        progress: ProgressBar,
         receiver: crossbeam_channel::Receiver<Job>,
         sender: crossbeam_channel::Sender<JobResult>,
         kill_trigger: crossbeam_channel::Receiver<()>,
         job_running: Arc<AtomicBool>,
+        config: WorkerConfig,
     ) -> Self {
         Self {
             rootdir,
             test,
+            progress,
             receiver,
             sender,
             kill_trigger,
             job_running,
+            config,
         }
     }
 
@@ -166,36 +266,239 @@ impl<T: Test> WorkerThread<T> {
     fn run_job(&self, job: Job) -> anyhow::Result<JobStatus> {
         let workdir = self.workdir();
         let tmpdir = self.tmpdir();
-        let filepath = workdir.join(&job.path);
-        let tmpfilepath = tmpdir.join(&job.path);
+        let paths = job.paths().collect::<Vec<_>>();
 
-        if let Some(parent) = tmpfilepath.parent() {
-            std::fs::create_dir_all(parent).with_context(|| {
-                format!("recursively creating directory {parent:?} before pass {job:?}")
-            })?;
+        if self.config.git_backup {
+            // Stage the files, so that they can be restored from the index
+            git(self.rootdir(), &["add", "-f"], &paths)
+                .with_context(|| format!("saving files {paths:?} before pass {job:?}"))?;
+        } else {
+            for path in &paths {
+                let (filepath, tmpfilepath) = (workdir.join(path), tmpdir.join(path));
+                if let Some(parent) = tmpfilepath.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        format!("recursively creating directory {parent:?} before pass {job:?}")
+                    })?;
+                }
+                std::fs::copy(&filepath, &tmpfilepath)
+                    .with_context(|| format!("saving file {tmpfilepath:?} before pass {job:?}"))?;
+            }
         }
-        std::fs::copy(&filepath, &tmpfilepath)
-            .with_context(|| format!("saving file {tmpfilepath:?} before pass {job:?}"))?;
 
-        let res = job
+        let reporting_test = ReportingTest {
+            test: self.test.clone(),
+            progress: self.progress.clone(),
+            edited: job.path.clone(),
+            cache: self.config.test_cache.clone(),
+            tests_run: self.config.tests_run.clone(),
+            votes: self.config.votes,
+            retries: self.config.test_retries,
+        };
+        // Double-checking must actually re-run the test, not just hit the cache
+        let uncached_test = ReportingTest {
+            test: self.test.clone(),
+            progress: self.progress.clone(),
+            edited: job.path.clone(),
+            cache: None,
+            tests_run: self.config.tests_run.clone(),
+            votes: self.config.votes,
+            retries: self.config.test_retries,
+        };
+        let affects_interestingness = paths.iter().any(|p| self.test.affects_interestingness(p));
+        let (test, uncached_test): (&dyn Test, &dyn Test) = match affects_interestingness {
+            true => (&reporting_test, &uncached_test),
+            false => {
+                tracing::trace!("Skipping tests for {paths:?}, that cannot affect interestingness");
+                (&AssumeInteresting, &AssumeInteresting)
+            }
+        };
+        let mut res = job
             .pass
-            .reduce(&workdir, &self.test, &job, &self.kill_trigger)
+            .reduce(&workdir, test, &job, &self.kill_trigger)
             .with_context(|| format!("reducing with pass {job:?}"))?;
 
-        if !res.did_reduce() {
-            std::fs::copy(&tmpfilepath, &filepath).with_context(|| {
-                format!("restoring file {tmpfilepath:?} after failed pass {job:?}")
+        if let (true, JobStatus::Reduced(desc)) = (self.config.double_check, &res) {
+            let attempt_name = format!("Double-checking: {desc}");
+            match uncached_test
+                .test_interesting(
+                    &workdir,
+                    &self.kill_trigger,
+                    &attempt_name,
+                    job.id(usize::MAX),
+                )
+                .context("double-checking the reduction")?
+            {
+                TestResult::Interesting => (),
+                TestResult::NotInteresting => {
+                    tracing::warn!("Reduction was not interesting when tested a second time, your test is probably flaky: {desc}");
+                    res = JobStatus::DidNotReduce;
+                }
+                TestResult::Interrupted => res = JobStatus::Interrupted,
+            }
+        }
+
+        if self.config.git_backup {
+            match &res {
+                // Committing the reductions gives a history of the reduction for free
+                JobStatus::Reduced(desc) => git(
+                    self.rootdir(),
+                    &["commit", "-q", "--no-verify", "--allow-empty", "-m", desc],
+                    &paths,
+                )
+                .with_context(|| format!("committing files {paths:?} after pass {job:?}"))?,
+                _ => git(self.rootdir(), &["checkout", "-q"], &paths).with_context(|| {
+                    format!("restoring files {paths:?} after failed pass {job:?}")
+                })?,
+            }
+            return Ok(res);
+        }
+
+        for path in &paths {
+            let (filepath, tmpfilepath) = (workdir.join(path), tmpdir.join(path));
+            if !res.did_reduce() {
+                // The pass could have removed the file along with its directory
+                if let Some(parent) = filepath.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        format!(
+                            "recursively creating directory {parent:?} after failed pass {job:?}"
+                        )
+                    })?;
+                }
+                std::fs::copy(&tmpfilepath, &filepath).with_context(|| {
+                    format!("restoring file {tmpfilepath:?} after failed pass {job:?}")
+                })?;
+            }
+            std::fs::remove_file(&tmpfilepath).with_context(|| {
+                format!("removing temporary file {tmpfilepath:?} after pass {job:?}")
             })?;
         }
-        std::fs::remove_file(&tmpfilepath).with_context(|| {
-            format!("removing temporary file {tmpfilepath:?} after pass {job:?}")
-        })?;
 
         Ok(res)
     }
 }
 
-struct ReportingTest<T>(Arc<T>, ProgressBar);
+/// Run `git <args> [-- <paths>]` on the workdir of worker directory `rootdir`
+///
+/// The repository lives next to the workdir rather than in it, so that the tests and
+/// the snapshots never see it. `paths` are relative to the workdir.
+fn git(rootdir: &Path, args: &[&str], paths: &[&Path]) -> anyhow::Result<()> {
+    let dir = rootdir.join(WORKDIR);
+    let mut git_dir = OsString::from("--git-dir=");
+    git_dir.push(rootdir.join(GITDIR));
+    let mut work_tree = OsString::from("--work-tree=");
+    work_tree.push(&dir);
+    let mut command = std::process::Command::new("git");
+    command
+        .arg(git_dir)
+        .arg(work_tree)
+        .args(["-c", "user.name=tree-sitter-reduce"])
+        .args(["-c", "user.email=tree-sitter-reduce@localhost"])
+        .args(["-c", "commit.gpgsign=false"])
+        .args(args);
+    if !paths.is_empty() {
+        command.arg("--").args(paths.iter().map(|p| p.as_os_str()));
+    }
+    let output = command
+        .current_dir(&dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .with_context(|| format!("running git {args:?} in {dir:?}"))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git {args:?} failed in {dir:?} ({}):\n---\n{}---",
+        output.status,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    Ok(())
+}
+
+/// Wrapper around the user-provided test, that reports progress and runs the smoke test
+struct ReportingTest<T> {
+    test: Arc<T>,
+    progress: ProgressBar,
+
+    /// Path to the file being edited by the current job
+    edited: PathBuf,
+
+    cache: Option<Arc<TestCache>>,
+
+    /// Counter of the test runs, see `WorkerConfig::tests_run`
+    tests_run: Arc<AtomicU64>,
+
+    votes: usize,
+
+    /// Number of times to retry the test when it returns an error
+    retries: usize,
+}
+
+impl<T: Test> ReportingTest<T> {
+    /// Run the test, retrying it up to `retries` times if it returns an error
+    ///
+    /// Errors make the worker get rebuilt from scratch, so this avoids doing it on
+    /// transient failures.
+    fn run_with_retries(
+        &self,
+        root: &Path,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+        attempt_name: &str,
+        attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        let mut retry = 0;
+        loop {
+            self.tests_run.fetch_add(1, Ordering::Relaxed);
+            let e = match self
+                .test
+                .test_interesting(root, kill_trigger, attempt_name, attempt_id)
+            {
+                Err(e) if retry < self.retries => e,
+                res => return res,
+            };
+            retry += 1;
+            tracing::warn!("Test failed to run, retrying in {TEST_RETRY_DELAY:?}: {e:#}");
+            match kill_trigger.recv_timeout(TEST_RETRY_DELAY) {
+                Ok(()) => return Ok(TestResult::Interrupted),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(e @ RecvTimeoutError::Disconnected) => {
+                    return Err(e).context("waiting for kill trigger")
+                }
+            }
+        }
+    }
+
+    /// Run the test up to `votes` times, returning the result of the strict majority
+    ///
+    /// Ties count as not interesting. This stops as soon as the majority is known,
+    /// and checks `kill_trigger` between the runs.
+    fn vote(
+        &self,
+        root: &Path,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+        attempt_name: &str,
+        attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        let (mut interesting, mut not_interesting) = (0, 0);
+        for vote in 0..self.votes {
+            if vote > 0 && kill_trigger.try_recv().is_ok() {
+                return Ok(TestResult::Interrupted);
+            }
+            match self.run_with_retries(root, kill_trigger, attempt_name, attempt_id)? {
+                TestResult::Interesting => interesting += 1,
+                TestResult::NotInteresting => not_interesting += 1,
+                TestResult::Interrupted => return Ok(TestResult::Interrupted),
+            }
+            if interesting * 2 > self.votes || not_interesting * 2 >= self.votes {
+                break;
+            }
+        }
+        if interesting > 0 && not_interesting > 0 {
+            tracing::debug!("Test was interesting {interesting} times and not interesting {not_interesting} times on the same input: {attempt_name}");
+        }
+        Ok(match interesting * 2 > self.votes {
+            true => TestResult::Interesting,
+            false => TestResult::NotInteresting,
+        })
+    }
+}
 
 impl<T: Test> Test for ReportingTest<T> {
     fn test_interesting(
@@ -205,17 +508,55 @@ impl<T: Test> Test for ReportingTest<T> {
         attempt_name: &str,
         attempt_id: u64,
     ) -> anyhow::Result<TestResult> {
-        self.1.set_prefix(format!("#{:04x}", attempt_id % 0xFFFF));
-        self.1.set_message(String::from(attempt_name));
-        let res = self
-            .0
-            .test_interesting(root, kill_trigger, attempt_name, attempt_id);
-        self.1
+        self.progress
+            .set_prefix(format!("#{:04x}", attempt_id % 0xFFFF));
+        self.progress.set_message(String::from(attempt_name));
+        let cached = match &self.cache {
+            Some(cache) => {
+                let state = cache.hash_state(root)?;
+                Some((cache, state, cache.get(state)))
+            }
+            None => None,
+        };
+        let res = match cached {
+            Some((_, _, Some(res))) => {
+                tracing::trace!("Reusing cached test result for {attempt_name}");
+                Ok(res)
+            }
+            _ => match self.test.smoke_test(root, &self.edited) {
+                Ok(true) => self.vote(root, kill_trigger, attempt_name, attempt_id),
+                Ok(false) => {
+                    tracing::trace!("Smoke test failed, skipping test for {attempt_name}");
+                    Ok(TestResult::NotInteresting)
+                }
+                Err(e) => Err(e).context("running the smoke test"),
+            },
+        };
+        if let (Some((cache, state, None)), Ok(res)) = (cached, &res) {
+            cache.insert(state, *res);
+        }
+        self.progress
             .set_message("Figuring out which pass to attempt next");
         res
     }
 
     fn cleanup_snapshot(&self, root: &Path) -> anyhow::Result<()> {
-        self.0.cleanup_snapshot(root)
+        self.test.cleanup_snapshot(root)
+    }
+
+    fn affects_interestingness(&self, path: &Path) -> bool {
+        self.test.affects_interestingness(path)
+    }
+
+    fn smoke_test(&self, root: &Path, edited: &Path) -> anyhow::Result<bool> {
+        self.test.smoke_test(root, edited)
+    }
+
+    fn identity(&self) -> Option<u64> {
+        self.test.identity()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.test.description()
     }
 }
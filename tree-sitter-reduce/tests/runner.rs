@@ -0,0 +1,754 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tree_sitter_reduce::{
+    passes::generic::{
+        DiscardWhitespace, EditGroup, RemoveLines, TreeSitterGroupReplace, TreeSitterQueryReplace,
+    },
+    FnTest, Job, JobStatus, Opt, Pass, ReductionEvent, StdinTest, Test, TestResult,
+};
+
+const MARKER: &str = "this line triggers the bug";
+
+/// Test that deems the input interesting iff `input.txt` still contains `MARKER`
+struct ContainsMarker;
+
+impl Test for ContainsMarker {
+    fn test_interesting(
+        &self,
+        root: &Path,
+        _kill_trigger: &crossbeam_channel::Receiver<()>,
+        _attempt_name: &str,
+        _attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        let contents = std::fs::read_to_string(root.join("input.txt"))?;
+        Ok(match contents.contains(MARKER) {
+            true => TestResult::Interesting,
+            false => TestResult::NotInteresting,
+        })
+    }
+
+    fn cleanup_snapshot(&self, _root: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+fn latest_snapshot(snap_dir: &Path) -> PathBuf {
+    let mut snapshots = std::fs::read_dir(snap_dir)
+        .unwrap()
+        .map(|s| s.unwrap().path())
+        .filter(|s| !s.file_name().unwrap().to_string_lossy().starts_with('.'))
+        .collect::<Vec<_>>();
+    snapshots.sort();
+    snapshots.pop().expect("No snapshot was taken")
+}
+
+/// Reduce `files` in `root` with a single job and a fixed seed, returning the events
+///
+/// The reduction converges after 20 jobs in a row without reduction. `tweak` can
+/// change these options, or any other, before running.
+fn run_reduction(
+    root: &Path,
+    snap_dir: &Path,
+    files: &'static [&'static str],
+    test: impl Test,
+    passes: &[Arc<dyn Pass>],
+    tweak: impl FnOnce(&mut Opt),
+) -> anyhow::Result<Vec<ReductionEvent>> {
+    let mut opt = Opt::new(root.to_path_buf(), snap_dir.to_path_buf());
+    opt.jobs = 1;
+    opt.random_seed = Some(42);
+    opt.converge_after = Some(20);
+    tweak(&mut opt);
+    // Keep the sender alive, as the runner considers a disconnection to be a bug
+    let (_killer, kill_trigger) = crossbeam_channel::bounded(1);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    tree_sitter_reduce::reduce(
+        opt,
+        |root| {
+            // Even single files are reduced within a directory
+            assert!(root.is_dir());
+            Ok(files.iter().map(PathBuf::from).collect())
+        },
+        test,
+        passes,
+        &[],
+        kill_trigger,
+        {
+            let events = events.clone();
+            move |e: &ReductionEvent| events.lock().unwrap().push(e.clone())
+        },
+    )?;
+    let events = events.lock().unwrap().clone();
+    Ok(events)
+}
+
+#[test]
+fn reduces_to_marker() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    let mut input = String::new();
+    for i in 0..50 {
+        input.push_str(&format!("unrelated line {i}   \n"));
+        if i == 30 {
+            input.push_str(&format!("  {MARKER}\n\n"));
+        }
+    }
+    std::fs::write(root.path().join("input.txt"), input).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![
+        Arc::new(RemoveLines),
+        Arc::new(DiscardWhitespace { extensions: &[] }),
+    ];
+    let events = run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt"],
+        ContainsMarker,
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    let reduced =
+        std::fs::read_to_string(latest_snapshot(snap_dir.path()).join("input.txt")).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+    assert!(!reduced.contains("unrelated"));
+
+    assert!(events.iter().any(|e| matches!(
+        e,
+        ReductionEvent::Reduced { old_size, new_size, .. } if new_size < old_size
+    )));
+    assert!(matches!(
+        events.last(),
+        Some(ReductionEvent::Snapshot { total_size, .. }) if *total_size == reduced.len() as u64
+    ));
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, ReductionEvent::Converged { .. })));
+}
+
+#[test]
+fn reduces_with_closure_test() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("input.txt"), "a\nb\nkeep me\nc\n").unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt"],
+        FnTest::new(|root: &Path, _: &crossbeam_channel::Receiver<()>| {
+            let contents = std::fs::read_to_string(root.join("input.txt"))?;
+            Ok(match contents.contains("keep me") {
+                true => TestResult::Interesting,
+                false => TestResult::NotInteresting,
+            })
+        }),
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    let reduced =
+        std::fs::read_to_string(latest_snapshot(snap_dir.path()).join("input.txt")).unwrap();
+    assert_eq!(reduced.trim(), "keep me");
+}
+
+#[test]
+fn rejects_uninteresting_input() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("input.txt"), "nothing to see here\n").unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    let res = run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt"],
+        ContainsMarker,
+        &passes,
+        |_| (),
+    );
+    assert!(res.is_err());
+}
+
+#[test]
+fn rejects_formatting_changed_only_snapshots() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("input.txt"), format!("a\n{MARKER}\n")).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    let res = run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt"],
+        ContainsMarker,
+        &passes,
+        |opt| {
+            opt.changed_only = true;
+            opt.format_command = Some(PathBuf::from("true"));
+        },
+    );
+    assert!(res.is_err());
+}
+
+#[test]
+fn reduces_single_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("input.txt");
+    std::fs::write(&file, format!("a\nb\n{MARKER}\nc\n")).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    run_reduction(
+        &file,
+        snap_dir.path(),
+        &["input.txt"],
+        ContainsMarker,
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    let reduced = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+}
+
+#[cfg(unix)]
+#[test]
+fn reduces_single_file_through_stdin() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("input.txt");
+    std::fs::write(&file, format!("a\nb\n{MARKER}\nc\n")).unwrap();
+    let command = dir.path().join("test.sh");
+    std::fs::write(
+        &command,
+        format!(
+            "#!/bin/sh\nif grep -q '{MARKER}'; then echo interesting; else echo not interesting; fi\n"
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    let run = |root: &Path| {
+        run_reduction(
+            root,
+            snap_dir.path(),
+            &["input.txt"],
+            StdinTest::new(command.clone()),
+            &passes,
+            |_| (),
+        )
+    };
+    assert!(run(dir.path()).is_err());
+    run(&file).unwrap();
+
+    let reduced = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+}
+
+#[cfg(unix)]
+#[test]
+fn resumes_single_file_reductions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("input.txt");
+    std::fs::write(&file, format!("a\nb\n{MARKER}\nc\n")).unwrap();
+    let command = dir.path().join("test.sh");
+    std::fs::write(
+        &command,
+        format!(
+            "#!/bin/sh\nif grep -q '{MARKER}'; then echo interesting; else echo not interesting; fi\n"
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    let run = |resume: bool| {
+        run_reduction(
+            &file,
+            snap_dir.path(),
+            &["input.txt"],
+            StdinTest::new(command.clone()),
+            &passes,
+            |opt| opt.resume = resume,
+        )
+    };
+    run(false).unwrap();
+    // The result of the resumed reduction must be written back to the file too
+    std::fs::write(&file, format!("a\nb\n{MARKER}\nc\n")).unwrap();
+    run(true).unwrap();
+
+    let reduced = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+}
+
+/// `RemoveLines`, but only managing to reduce `a.txt`
+#[derive(Debug, Hash)]
+struct RemoveLinesOfA;
+
+impl Pass for RemoveLinesOfA {
+    fn reduce(
+        &self,
+        workdir: &Path,
+        test: &dyn Test,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        if job.path != Path::new("a.txt") {
+            return Ok(JobStatus::PassFailed(String::from("Not a.txt")));
+        }
+        RemoveLines.reduce(workdir, test, job, kill_trigger)
+    }
+}
+
+#[test]
+fn replays_edits_on_symmetric_files() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    let input = format!("alpha\nbeta\n{MARKER}\ngamma\ndelta\n");
+    std::fs::write(root.path().join("a.txt"), &input).unwrap();
+    std::fs::write(root.path().join("b.txt"), &input).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLinesOfA)];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["a.txt", "b.txt"],
+        FnTest::new(|root: &Path, _: &crossbeam_channel::Receiver<()>| {
+            for file in ["a.txt", "b.txt"] {
+                if !std::fs::read_to_string(root.join(file))?.contains(MARKER) {
+                    return Ok(TestResult::NotInteresting);
+                }
+            }
+            Ok(TestResult::Interesting)
+        }),
+        &passes,
+        |opt| opt.symmetric_files = true,
+    )
+    .unwrap();
+
+    let snapshot = latest_snapshot(snap_dir.path());
+    for file in ["a.txt", "b.txt"] {
+        let reduced = std::fs::read_to_string(snapshot.join(file)).unwrap();
+        assert_eq!(reduced.trim(), MARKER, "{file} was not reduced");
+    }
+}
+
+/// Pass removing the `dir` directory and its mention in `list.txt` at once
+#[derive(Debug, Hash)]
+struct RemoveDir;
+
+impl Pass for RemoveDir {
+    fn reduce(
+        &self,
+        workdir: &Path,
+        test: &dyn Test,
+        job: &Job,
+        kill_trigger: &crossbeam_channel::Receiver<()>,
+    ) -> anyhow::Result<JobStatus> {
+        std::fs::remove_dir_all(workdir.join("dir"))?;
+        std::fs::write(workdir.join("list.txt"), "")?;
+        match test.test_interesting(workdir, kill_trigger, "Remove dir", job.id(0))? {
+            TestResult::Interesting => Ok(JobStatus::Reduced(String::from("Remove dir"))),
+            TestResult::NotInteresting => Ok(JobStatus::DidNotReduce),
+            TestResult::Interrupted => Ok(JobStatus::Interrupted),
+        }
+    }
+
+    fn applies_to(&self, path: &Path) -> bool {
+        path == Path::new("dir/a.txt")
+    }
+
+    fn extra_paths(&self, _workdir: &Path, _path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(vec![PathBuf::from("dir/b.txt"), PathBuf::from("list.txt")])
+    }
+}
+
+#[test]
+fn removes_files_listed_as_extra_paths() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(root.path().join("dir")).unwrap();
+    std::fs::write(root.path().join("dir/a.txt"), "alpha\nbeta\n").unwrap();
+    std::fs::write(root.path().join("dir/b.txt"), "gamma\ndelta\n").unwrap();
+    std::fs::write(root.path().join("list.txt"), "dir\n").unwrap();
+    std::fs::write(root.path().join("input.txt"), format!("alpha\n{MARKER}\n")).unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines), Arc::new(RemoveDir)];
+    let events = run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt", "dir/a.txt", "dir/b.txt"],
+        FnTest::new(|root: &Path, _: &crossbeam_channel::Receiver<()>| {
+            let input = std::fs::read_to_string(root.join("input.txt"))?;
+            let list = std::fs::read_to_string(root.join("list.txt"))?;
+            // Like a workspace whose members must all exist
+            let consistent = list.contains("dir") == root.join("dir").exists();
+            Ok(match input.contains(MARKER) && consistent {
+                true => TestResult::Interesting,
+                false => TestResult::NotInteresting,
+            })
+        }),
+        &passes,
+        |opt| opt.jobs = 2,
+    )
+    .unwrap();
+
+    let snapshot = latest_snapshot(snap_dir.path());
+    assert!(!snapshot.join("dir").exists());
+    assert_eq!(
+        std::fs::read_to_string(snapshot.join("list.txt")).unwrap(),
+        ""
+    );
+    let reduced = std::fs::read_to_string(snapshot.join("input.txt")).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+    assert!(matches!(
+        events.last(),
+        Some(ReductionEvent::Snapshot { total_size, .. }) if *total_size == reduced.len() as u64
+    ));
+}
+
+#[test]
+fn stops_once_all_files_are_removed() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(root.path().join("dir")).unwrap();
+    std::fs::write(root.path().join("dir/a.txt"), "alpha\n").unwrap();
+    std::fs::write(root.path().join("dir/b.txt"), "beta\n").unwrap();
+    std::fs::write(root.path().join("list.txt"), "dir\n").unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveDir)];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["dir/a.txt", "dir/b.txt"],
+        FnTest::new(|_: &Path, _: &crossbeam_channel::Receiver<()>| Ok(TestResult::Interesting)),
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    assert!(!latest_snapshot(snap_dir.path()).join("dir").exists());
+}
+
+#[test]
+fn keeps_extra_paths_within_the_selected_files() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(root.path().join("dir")).unwrap();
+    std::fs::write(root.path().join("dir/a.txt"), "alpha\nbeta\n").unwrap();
+    std::fs::write(root.path().join("dir/b.txt"), "gamma\ndelta\n").unwrap();
+    std::fs::write(root.path().join("list.txt"), "dir\n").unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines), Arc::new(RemoveDir)];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &[],
+        FnTest::new(|_: &Path, _: &crossbeam_channel::Receiver<()>| Ok(TestResult::Interesting)),
+        &passes,
+        // Like a shard that does not contain `dir/b.txt`
+        |opt| opt.only_files = Some(vec![PathBuf::from("dir/a.txt")]),
+    )
+    .unwrap();
+
+    let snapshot = latest_snapshot(snap_dir.path());
+    assert_eq!(
+        std::fs::read_to_string(snapshot.join("dir/b.txt")).unwrap(),
+        "gamma\ndelta\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(snapshot.join("list.txt")).unwrap(),
+        "dir\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(snapshot.join("dir/a.txt")).unwrap(),
+        ""
+    );
+}
+
+#[test]
+fn resumes_from_changed_only_snapshots() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    let input = (0..20)
+        .map(|i| format!("unrelated line {i}\n"))
+        .collect::<String>();
+    std::fs::write(root.path().join("input.txt"), format!("{input}{MARKER}\n")).unwrap();
+    std::fs::write(root.path().join("other.txt"), "keep\n").unwrap();
+
+    let test = || {
+        FnTest::new(|root: &Path, _: &crossbeam_channel::Receiver<()>| {
+            // Snapshot names only have a millisecond precision
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            let input = std::fs::read_to_string(root.join("input.txt"))?;
+            let other = std::fs::read_to_string(root.join("other.txt"))?;
+            Ok(match input.contains(MARKER) && other == "keep\n" {
+                true => TestResult::Interesting,
+                false => TestResult::NotInteresting,
+            })
+        })
+    };
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    let run = |tweak: fn(&mut Opt)| {
+        run_reduction(
+            root.path(),
+            snap_dir.path(),
+            &["input.txt"],
+            test(),
+            &passes,
+            tweak,
+        )
+    };
+
+    run(|opt| {
+        opt.snapshot_interval = 0;
+        opt.changed_only = true;
+    })
+    .unwrap();
+    let snapshot = latest_snapshot(snap_dir.path());
+    assert!(snapshot.join(".tree-sitter-reduce-layer").exists());
+    assert!(!snapshot.join("other.txt").exists());
+
+    // The test fails unless the whole tree is rebuilt from the layers
+    run(|opt| opt.resume = true).unwrap();
+    let snapshot = latest_snapshot(snap_dir.path());
+    let reduced = std::fs::read_to_string(snapshot.join("input.txt")).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+    assert_eq!(
+        std::fs::read_to_string(snapshot.join("other.txt")).unwrap(),
+        "keep\n"
+    );
+}
+
+#[test]
+fn retries_tests_that_fail_to_run() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        root.path().join("input.txt"),
+        format!("a\nb\n{MARKER}\nc\n"),
+    )
+    .unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    let roots = Arc::new(Mutex::new(Vec::new()));
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt"],
+        FnTest::new({
+            let roots = roots.clone();
+            move |root: &Path, _: &crossbeam_channel::Receiver<()>| {
+                let mut roots = roots.lock().unwrap();
+                roots.push(root.to_path_buf());
+                // The first run validates the input, the second one is in a worker
+                anyhow::ensure!(roots.len() != 2, "transient failure");
+                let contents = std::fs::read_to_string(root.join("input.txt"))?;
+                Ok(match contents.contains(MARKER) {
+                    true => TestResult::Interesting,
+                    false => TestResult::NotInteresting,
+                })
+            }
+        }),
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    // The test was retried in the same worker, rather than in a rebuilt one
+    let roots = roots.lock().unwrap();
+    assert_eq!(roots[1], roots[2]);
+    let reduced =
+        std::fs::read_to_string(latest_snapshot(snap_dir.path()).join("input.txt")).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+}
+
+/// Test that only accepts the original `notes.txt`, declared to not matter
+struct IgnoresNotes;
+
+impl Test for IgnoresNotes {
+    fn test_interesting(
+        &self,
+        root: &Path,
+        _kill_trigger: &crossbeam_channel::Receiver<()>,
+        _attempt_name: &str,
+        _attempt_id: u64,
+    ) -> anyhow::Result<TestResult> {
+        let notes = std::fs::read_to_string(root.join("notes.txt"))?;
+        Ok(match notes == "a\nb\nc\n" {
+            true => TestResult::Interesting,
+            false => TestResult::NotInteresting,
+        })
+    }
+
+    fn cleanup_snapshot(&self, _root: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn affects_interestingness(&self, path: &Path) -> bool {
+        path != Path::new("notes.txt")
+    }
+}
+
+#[test]
+fn skips_tests_for_files_not_affecting_interestingness() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("notes.txt"), "a\nb\nc\n").unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["notes.txt"],
+        IgnoresNotes,
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    // Running the test would have rejected any edit
+    let reduced =
+        std::fs::read_to_string(latest_snapshot(snap_dir.path()).join("notes.txt")).unwrap();
+    assert_eq!(reduced, "");
+}
+
+#[test]
+fn keeps_git_backups_out_of_the_tree() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        root.path().join("input.txt"),
+        format!("a\nb\n{MARKER}\nc\n"),
+    )
+    .unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(RemoveLines)];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.txt"],
+        FnTest::new(|root: &Path, _: &crossbeam_channel::Receiver<()>| {
+            anyhow::ensure!(!root.join(".git").exists(), "found .git in {root:?}");
+            let contents = std::fs::read_to_string(root.join("input.txt"))?;
+            Ok(match contents.contains(MARKER) {
+                true => TestResult::Interesting,
+                false => TestResult::NotInteresting,
+            })
+        }),
+        &passes,
+        |opt| {
+            opt.jobs = 2;
+            opt.git_backup = true;
+        },
+    )
+    .unwrap();
+
+    let snapshot = latest_snapshot(snap_dir.path());
+    assert!(!snapshot.join(".git").exists());
+    let reduced = std::fs::read_to_string(snapshot.join("input.txt")).unwrap();
+    assert_eq!(reduced.trim(), MARKER);
+}
+
+/// Groups removing `a` and `b`, and `b` and `c`, when they are all in the input
+fn overlapping_groups(input: &[u8], _: &tree_sitter::Node) -> Vec<EditGroup> {
+    let pos = |c| input.iter().position(|&b| b == c);
+    let (Some(a), Some(b), Some(c)) = (pos(b'a'), pos(b'b'), pos(b'c')) else {
+        return Vec::new();
+    };
+    vec![
+        vec![(a..a + 1, Vec::new()), (b..b + 1, Vec::new())],
+        vec![(b..b + 1, Vec::new()), (c..c + 1, Vec::new())],
+    ]
+}
+
+#[test]
+fn applies_overlapping_groups_as_a_whole() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("input.rs"), "abc\n").unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(TreeSitterGroupReplace {
+        language: tree_sitter_rust::language(),
+        extensions: &["rs"],
+        name: String::from("Overlapping groups"),
+        group_finder: overlapping_groups,
+    })];
+    let tested = Arc::new(Mutex::new(Vec::new()));
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.rs"],
+        FnTest::new({
+            let tested = tested.clone();
+            move |root: &Path, _: &crossbeam_channel::Receiver<()>| {
+                let contents = std::fs::read_to_string(root.join("input.rs"))?;
+                tested.lock().unwrap().push(contents);
+                Ok(TestResult::Interesting)
+            }
+        }),
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    // Applying only half of a group would remove all three letters
+    let tested = tested.lock().unwrap();
+    assert!(tested.len() > 1);
+    for contents in tested.iter() {
+        assert!(
+            ["abc\n", "a\n", "c\n"].contains(&&contents[..]),
+            "{contents:?}"
+        );
+    }
+}
+
+#[test]
+fn ignores_missing_nodes_in_query_replacements() {
+    let root = tempfile::tempdir().unwrap();
+    let snap_dir = tempfile::tempdir().unwrap();
+    // The `;` after `1` is a zero-width `MISSING` node
+    std::fs::write(
+        root.path().join("input.rs"),
+        "fn f() { let x = 1; let y = 2 }\n",
+    )
+    .unwrap();
+
+    let passes: Vec<Arc<dyn Pass>> = vec![Arc::new(
+        TreeSitterQueryReplace::new(
+            tree_sitter_rust::language(),
+            &["rs"],
+            String::from("Remove semicolons"),
+            r#"(let_declaration ";" @replace)"#,
+            "",
+        )
+        .unwrap(),
+    )];
+    run_reduction(
+        root.path(),
+        snap_dir.path(),
+        &["input.rs"],
+        FnTest::new(|_: &Path, _: &crossbeam_channel::Receiver<()>| Ok(TestResult::Interesting)),
+        &passes,
+        |_| (),
+    )
+    .unwrap();
+
+    let reduced =
+        std::fs::read_to_string(latest_snapshot(snap_dir.path()).join("input.rs")).unwrap();
+    assert_eq!(reduced, "fn f() { let x = 1 let y = 2 }\n");
+}